@@ -0,0 +1,99 @@
+use anyhow::{bail, Context, Result};
+use libflipnote::ppm::{
+    exporters::terminal_exporter::{
+        detect_graphics, get_terminal_size, write_half_blocks, write_sixel, TerminalGraphics,
+        TerminalPlayerOptions,
+    },
+    file::PPMFile,
+    frames::render_options::RenderOptions,
+};
+
+const USAGE: &str = "Usage: preview <file.ppm> [--frame N] [--play] [--blocks | --sixel] [--scale N]
+
+Plays the flipnote in the terminal. Space pauses, the arrow keys step through the frames and q quits.
+  --frame N  Only prints frame N, or starts playing from it with --play
+  --play     Plays even when --frame is given
+  --blocks   Draws with colored half-block characters
+  --sixel    Draws with sixel graphics
+  --scale N  Scale of sixel images";
+
+pub fn main() {
+    if let Err(error) = run() {
+        eprintln!("{error:#}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let mut path = None;
+    let mut frame = None;
+    let mut play = false;
+    let mut graphics = None;
+    let mut render_options = RenderOptions::default();
+
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frame" => frame = Some(parse_number(args.next(), "--frame")?),
+            "--play" => play = true,
+            "--blocks" => graphics = Some(TerminalGraphics::HalfBlocks),
+            "--sixel" => graphics = Some(TerminalGraphics::Sixel),
+            "--scale" => render_options.scale = parse_number(args.next(), "--scale")? as u32,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => bail!("Unknown argument {arg}\n\n{USAGE}"),
+        }
+    }
+
+    let Some(path) = path else {
+        bail!("{USAGE}");
+    };
+
+    let file = PPMFile::from_path(&path).with_context(|| format!("Couldn't read {path}"))?;
+
+    match frame {
+        Some(index) if !play => {
+            let frames = file.animation_data.get_frames()?;
+
+            let Some(frame) = frames.get(index) else {
+                bail!("{path} only has {} frames", frames.len());
+            };
+
+            let graphics = match graphics {
+                Some(graphics) => graphics,
+                None => detect_graphics().unwrap_or_default(),
+            };
+
+            let stdout = std::io::stdout().lock();
+
+            match graphics {
+                TerminalGraphics::HalfBlocks => {
+                    let width = get_terminal_size().0.min(256);
+
+                    write_half_blocks(frame, stdout, width, &render_options)?;
+                }
+                TerminalGraphics::Sixel => {
+                    write_sixel(frame, stdout, &render_options)?;
+                    println!();
+                }
+            }
+        }
+        _ => file.play_in_terminal(&TerminalPlayerOptions {
+            graphics,
+            render_options,
+            start_frame: frame.unwrap_or(0),
+        })?,
+    }
+
+    Ok(())
+}
+
+fn parse_number(value: Option<String>, name: &str) -> Result<usize> {
+    value
+        .and_then(|value| value.parse().ok())
+        .with_context(|| format!("{name} needs a number"))
+}
@@ -1,6 +1,6 @@
 use anyhow::{ensure, Result};
 use libflipnote::{
-    ppm::{constants::PPM_AUDIO_PLAYBACK_SAMPLE_RATE, file::PPMFile},
+    ppm::{exporters::video_exporter::VideoExportOptions, file::PPMFile},
     utils::image_utils::ImageWrapper,
 };
 
@@ -36,10 +36,7 @@ fn run() -> Result<()> {
     }
 
     // Export the video as an MP4 file. Requires ffmpeg to be installed.
-    ppm_file.export_video(
-        "/home/sarah/Videos/bokeh.mp4",
-        PPM_AUDIO_PLAYBACK_SAMPLE_RATE,
-    )?;
+    ppm_file.export_video("/home/sarah/Videos/bokeh.mp4", &VideoExportOptions::new())?;
 
     // Verify the signature
     ensure!(
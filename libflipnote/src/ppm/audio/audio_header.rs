@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Result, ensure};
 use binrw::binrw;
 
 use crate::ppm::constants::{PPM_AUDIO_SAMPLE_RATE, PPM_FRAMERATE};
@@ -12,7 +12,7 @@ pub struct PPMAudioHeader {
     #[br(count = args.0)]
     pub sound_effect_flags: Vec<u8>,
 
-    #[brw(pad_before = 4 - (args.1 % 4))]
+    #[brw(pad_before = (4 - (args.1 % 4)) % 4)]
     //Sound Header
     pub bgm_track_size: u32,
     pub se1_track_size: u32,
@@ -24,6 +24,43 @@ pub struct PPMAudioHeader {
 }
 
 impl PPMAudioHeader {
+    /// Returns the playback speed as shown in Flipnote Studio, from 1 (0.5 fps) to 8 (30 fps)
+    pub fn get_speed(&self) -> u8 {
+        8 - self.frame_playback_speed
+    }
+
+    pub fn set_speed(&mut self, speed: u8) -> Result<()> {
+        ensure!((1..=8).contains(&speed), "Speed must be between 1 and 8");
+
+        self.frame_playback_speed = 8 - speed;
+
+        Ok(())
+    }
+
+    /// Returns the playback speed the BGM was recorded at, from 1 (0.5 fps) to 8 (30 fps)
+    pub fn get_bgm_speed(&self) -> u8 {
+        8 - self.frame_playback_speed_when_recording
+    }
+
+    pub fn set_bgm_speed(&mut self, speed: u8) -> Result<()> {
+        ensure!((1..=8).contains(&speed), "Speed must be between 1 and 8");
+
+        self.frame_playback_speed_when_recording = 8 - speed;
+
+        Ok(())
+    }
+
+    /// Returns the speed whose framerate is closest to the given FPS
+    pub fn get_speed_for_framerate(framerate: f32) -> u8 {
+        (1..PPM_FRAMERATE.len())
+            .min_by(|a, b| {
+                (PPM_FRAMERATE[*a] - framerate)
+                    .abs()
+                    .total_cmp(&(PPM_FRAMERATE[*b] - framerate).abs())
+            })
+            .unwrap() as u8
+    }
+
     /// Returns the actual FPS of the animation
     pub fn get_framerate(&self) -> Result<f32> {
         let speed = 8 - self.frame_playback_speed;
@@ -35,6 +72,17 @@ impl PPMAudioHeader {
         Ok(PPM_FRAMERATE[speed as usize])
     }
 
+    /// Returns the FPS as an exact fraction, e.g. `(1, 2)` for 0.5 fps.
+    pub fn get_framerate_ratio(&self) -> Result<(u32, u32)> {
+        //every speed is a multiple of 0.5 fps.
+        let half_frames = (self.get_framerate()? * 2.0).round() as u32;
+
+        match half_frames % 2 {
+            0 => Ok((half_frames / 2, 1)),
+            _ => Ok((half_frames, 2)),
+        }
+    }
+
     /// Returns the actual FPS of the BGM when it was recorded
     pub fn get_bgm_framerate(&self) -> Result<f32> {
         let speed = 8 - self.frame_playback_speed_when_recording;
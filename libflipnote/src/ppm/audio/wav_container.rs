@@ -156,6 +156,50 @@ impl WavContainer {
         ))
     }
 
+    /// Returns the samples from `start` up to `end`, both clamped to the length of the track.
+    pub fn slice(&self, start: usize, end: usize) -> Self {
+        let end = end.min(self.buffer.len());
+        let start = start.min(end);
+
+        Self::from_samples(
+            self.buffer[start..end].to_vec(),
+            self.channels,
+            self.sample_rate,
+            self.bits_per_sample,
+        )
+    }
+
+    /// Cuts the track to `length` samples, or pads it with silence if it is shorter.
+    pub fn with_length(&self, length: usize) -> Self {
+        let mut buffer = self.buffer.clone();
+        buffer.resize(length, 0);
+
+        Self::from_samples(
+            buffer,
+            self.channels,
+            self.sample_rate,
+            self.bits_per_sample,
+        )
+    }
+
+    /// Appends another track, resampling it to this track's sample rate first.
+    pub fn concat(&self, other: &Self) -> Result<Self> {
+        ensure!(
+            self.channels == other.channels,
+            "Channels must be the same for concatenating"
+        );
+
+        let mut buffer = self.buffer.clone();
+        buffer.extend(other.resample(self.sample_rate)?.get_samples());
+
+        Ok(Self::from_samples(
+            buffer,
+            self.channels,
+            self.sample_rate,
+            self.bits_per_sample,
+        ))
+    }
+
     pub fn get_samples(&self) -> Vec<i16> {
         self.buffer.to_owned()
     }
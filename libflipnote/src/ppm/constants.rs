@@ -30,8 +30,16 @@ pub const PPM_COLOR_BLUE: RgbWrapper = RgbWrapper {
     b: 255,
 };
 
+pub const PPM_MAX_FRAME_COUNT: usize = 999;
+
+//how far the encoder looks in each direction when searching for the translation of a diffed frame.
+pub const PPM_TRANSLATE_SEARCH_RANGE: i8 = 16;
+
 pub const PPM_FRAMERATE: [f32; 9] = [0.5, 0.5, 1.0, 2.0, 4.0, 6.0, 12.0, 20.0, 30.0];
 
+//timestamps are stored as seconds since 2000-01-01 00:00:00 UTC.
+pub const PPM_TIMESTAMP_EPOCH: u64 = 946684800;
+
 pub const ADPCM_STATE_HEADER_SIZE: usize = 4;
 
 // Flipnote Studio public key, used to verify the signature of a PPM file.
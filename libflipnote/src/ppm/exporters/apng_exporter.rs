@@ -0,0 +1,105 @@
+use std::io::Write;
+
+use anyhow::Result;
+use png::{BitDepth, BlendOp, ColorType, DisposeOp, Encoder};
+
+use crate::ppm::file::PPMFile;
+
+use super::{AnimationExportOptions, get_frame_runs};
+
+/// Writes the flipnote as a lossless animated PNG.
+/// Runs of identical frames are merged into one longer frame. APNG delays are fractions of a second, so every speed is stored exactly.
+pub fn write_apng(
+    file: &PPMFile,
+    writer: impl Write,
+    options: &AnimationExportOptions,
+) -> Result<()> {
+    let render_options = &options.render_options;
+    render_options.validate()?;
+
+    let frames = file.animation_data.get_frames()?;
+    let (numerator, denominator) = file.audio.audio_header.get_framerate_ratio()?;
+
+    let runs = get_frame_runs(&frames, render_options)?;
+
+    let mut encoder = Encoder::new(
+        writer,
+        render_options.get_width(),
+        render_options.get_height(),
+    );
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+
+    //0 plays forever.
+    let plays = match file.animation_data.get_animation_flags().get_loop() {
+        true => 0,
+        false => 1,
+    };
+    encoder.set_animated(runs.len() as u32, plays)?;
+
+    let mut png_writer = encoder.write_header()?;
+
+    for run in runs {
+        //a run lasts length / fps seconds.
+        png_writer.set_frame_delay((run.length as u32 * denominator) as u16, numerator as u16)?;
+        png_writer.set_dispose_op(DisposeOp::None)?;
+        png_writer.set_blend_op(BlendOp::Source)?;
+
+        png_writer.write_image_data(
+            &frames[run.frame_index]
+                .get_image_with(render_options)?
+                .get_raw_pixels(),
+        )?;
+    }
+
+    png_writer.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use png::Decoder;
+
+    use super::*;
+    use crate::utils::test_utils::get_test_file;
+
+    /// Returns the number of plays and the delay of every frame as a fraction.
+    fn get_delays(file: &PPMFile) -> (u32, Vec<(u16, u16)>) {
+        let mut data = vec![];
+        write_apng(file, &mut data, &AnimationExportOptions::default()).unwrap();
+
+        let mut reader = Decoder::new(data.as_slice()).read_info().unwrap();
+        let animation = *reader.info().animation_control().unwrap();
+
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let mut delays = vec![];
+
+        for _ in 0..animation.num_frames {
+            reader.next_frame(&mut buffer).unwrap();
+
+            let control = reader.info().frame_control().unwrap();
+            delays.push((control.delay_num, control.delay_den));
+        }
+
+        (animation.num_plays, delays)
+    }
+
+    #[test]
+    fn apng_stores_exact_run_delays() {
+        //30 fps, the first picture shows for 2 frames.
+        let (plays, delays) = get_delays(&get_test_file(&[0, 0, 1], 8));
+
+        assert_eq!(plays, 1);
+        assert_eq!(delays, [(2, 30), (1, 30)]);
+
+        //0.5 fps is 2 seconds a frame.
+        let mut file = get_test_file(&[0, 1, 1], 1);
+        file.animation_data.get_animation_flags_mut().set_loop(true);
+
+        let (plays, delays) = get_delays(&file);
+
+        assert_eq!(plays, 0);
+        assert_eq!(delays, [(2, 1), (4, 1)]);
+    }
+}
@@ -0,0 +1,482 @@
+use std::{
+    cmp::Ordering,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Result, ensure};
+use serde_json::json;
+
+use crate::{
+    ppm::{file::PPMFile, frames::render_options::RenderOptions, palette::Palette},
+    utils::{
+        font_utils::{GLYPH_HEIGHT, draw_text, fit_text, get_text_width},
+        image_utils::{ImageWrapper, ResizeMode, RgbWrapper},
+    },
+};
+
+use super::frame_exporter::{format_frame_name, get_file_name};
+
+/// Space around every tile and between the picture and its caption, in pixels.
+const TILE_PADDING: u32 = 4;
+const CAPTION_LINES: u32 = 3;
+
+/// What the picture of a tile shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContactSheetTile {
+    /// The 64x48 thumbnail stored in the file, the quickest to draw.
+    #[default]
+    Thumbnail,
+    /// The frame the thumbnail was made from, rendered in full.
+    ThumbnailFrame,
+    /// The frame at this index, or the last frame of flipnotes that are shorter.
+    Frame(usize),
+}
+
+/// The order tiles are laid out in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContactSheetSortKey {
+    /// The order the paths were given in.
+    #[default]
+    None,
+    Author,
+    Date,
+    FrameCount,
+    Duration,
+    /// The flipnote's own file name, not the path it was read from.
+    FileName,
+}
+
+/// Options for [`export_contact_sheet`].
+#[derive(Debug, Clone)]
+pub struct ContactSheetOptions {
+    pub tile: ContactSheetTile,
+    pub columns: usize,
+    /// Rows of tiles on each page, `None` puts every flipnote on a single page.
+    pub rows_per_page: Option<usize>,
+    /// Pictures are 64x48 times this. Frames are scaled down to fit, and rendered at full size from a scale of 4.
+    pub tile_scale: u32,
+    /// Scale of the caption font, which is 5x7 pixels at 1.
+    pub caption_scale: u32,
+    pub sort_key: ContactSheetSortKey,
+    pub descending: bool,
+    /// Colors of the thumbnails and frames.
+    pub palette: Palette,
+    pub background: RgbWrapper,
+    pub text_color: RgbWrapper,
+}
+
+impl Default for ContactSheetOptions {
+    fn default() -> Self {
+        Self {
+            tile: ContactSheetTile::default(),
+            columns: 8,
+            rows_per_page: Some(8),
+            tile_scale: 1,
+            caption_scale: 1,
+            sort_key: ContactSheetSortKey::default(),
+            descending: false,
+            palette: Palette::default(),
+            background: RgbWrapper::new(32, 32, 32),
+            text_color: RgbWrapper::new(255, 255, 255),
+        }
+    }
+}
+
+/// What [`export_contact_sheet`] wrote.
+#[derive(Debug, Clone, Default)]
+pub struct ContactSheetExport {
+    /// Every page in order, each with a JSON file of the same name next to it.
+    pub pages: Vec<PathBuf>,
+    /// Files that couldn't be read as flipnotes and were left out.
+    pub skipped: Vec<PathBuf>,
+}
+
+/// A tile's picture and what its caption and sorting need, so files don't stay in memory until their page is drawn.
+struct SheetEntry {
+    path: PathBuf,
+    picture: ImageWrapper,
+    author: String,
+    file_name: String,
+    frame_count: usize,
+    duration: f32,
+    timestamp: SystemTime,
+}
+
+impl SheetEntry {
+    /// Reads the flipnote and draws its picture, the only time the file is parsed.
+    fn new(path: &Path, options: &ContactSheetOptions) -> Result<Self> {
+        let file = PPMFile::from_path(path)?;
+        let frame_count = file.get_frame_count();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            picture: get_picture(&file, options)?,
+            author: file.get_current_author_name(),
+            file_name: file.get_current_file_name(),
+            frame_count,
+            duration: frame_count as f32 / file.audio.audio_header.get_framerate()?,
+            timestamp: file.get_timestamp(),
+        })
+    }
+
+    fn get_caption(&self, width: u32, scale: u32) -> [String; CAPTION_LINES as usize] {
+        let date = format_timestamp(self.timestamp);
+
+        //drops the time before cutting the date short.
+        let date = match get_text_width(&date, scale) <= width {
+            true => date,
+            false => date[..10].to_string(),
+        };
+
+        [
+            self.author.clone(),
+            format!("{}f {:.1}s", self.frame_count, self.duration),
+            date,
+        ]
+        .map(|line| fit_text(&line, width, scale))
+    }
+}
+
+/// Draws a grid with a tile for every flipnote in `paths`, showing its picture and a caption with the author, frame count, duration and date.
+/// Pages are saved in `dir`, named by `pattern` like in [`export_frames`](super::frame_exporter::export_frames) with the page index, and each gets a JSON file listing which flipnote every tile is.
+/// The font only covers ASCII, other characters in author names are drawn as `?`.
+pub fn export_contact_sheet(
+    paths: &[PathBuf],
+    dir: impl Into<PathBuf>,
+    pattern: &str,
+    options: &ContactSheetOptions,
+) -> Result<ContactSheetExport> {
+    ensure!(
+        options.columns > 0,
+        "A contact sheet needs at least 1 column"
+    );
+    ensure!(
+        options.rows_per_page != Some(0),
+        "A contact sheet page needs at least 1 row"
+    );
+    ensure!(
+        (1..=16).contains(&options.tile_scale),
+        "Tile scale must be between 1 and 16"
+    );
+    ensure!(
+        (1..=8).contains(&options.caption_scale),
+        "Caption scale must be between 1 and 8"
+    );
+
+    let dir: PathBuf = dir.into();
+
+    std::fs::create_dir_all(&dir)?;
+
+    let mut export = ContactSheetExport::default();
+    let mut entries = Vec::with_capacity(paths.len());
+
+    for path in paths.iter() {
+        match SheetEntry::new(path, options) {
+            Ok(entry) => entries.push(entry),
+            Err(_) => export.skipped.push(path.clone()),
+        }
+    }
+
+    sort_entries(&mut entries, options.sort_key, options.descending);
+
+    let (picture_width, picture_height) = (64 * options.tile_scale, 48 * options.tile_scale);
+    let line_height = (GLYPH_HEIGHT + 2) * options.caption_scale;
+
+    let tile_width = picture_width + 2 * TILE_PADDING;
+    let tile_height = picture_height + 3 * TILE_PADDING + CAPTION_LINES * line_height;
+
+    let tiles_per_page = match options.rows_per_page {
+        Some(rows) => options.columns * rows,
+        None => entries.len().max(1),
+    };
+
+    for (page_index, page_entries) in entries.chunks(tiles_per_page).enumerate() {
+        let columns = options.columns.min(page_entries.len());
+        let rows = page_entries.len().div_ceil(columns);
+
+        let (width, height) = (columns as u32 * tile_width, rows as u32 * tile_height);
+        let background = [
+            options.background.r,
+            options.background.g,
+            options.background.b,
+            255,
+        ];
+
+        let mut page = ImageWrapper::from_raw_pixels(
+            width,
+            height,
+            background.repeat((width * height) as usize),
+        )?;
+
+        let mut tiles = Vec::with_capacity(page_entries.len());
+
+        for (i, entry) in page_entries.iter().enumerate() {
+            let x = (i % columns) as u32 * tile_width;
+            let y = (i / columns) as u32 * tile_height;
+
+            page.paste(&entry.picture, x + TILE_PADDING, y + TILE_PADDING);
+
+            let caption_y = y + picture_height + 2 * TILE_PADDING;
+
+            for (line_index, line) in entry
+                .get_caption(picture_width, options.caption_scale)
+                .iter()
+                .enumerate()
+            {
+                draw_text(
+                    &mut page,
+                    line,
+                    x + TILE_PADDING,
+                    caption_y + line_index as u32 * line_height,
+                    options.caption_scale,
+                    &options.text_color,
+                );
+            }
+
+            tiles.push(json!({
+                "path": entry.path.to_string_lossy(),
+                "x": x,
+                "y": y,
+                "width": tile_width,
+                "height": tile_height,
+                "author": entry.author,
+                "file_name": entry.file_name,
+                "frame_count": entry.frame_count,
+                "duration": entry.duration,
+                "timestamp": entry.timestamp.duration_since(UNIX_EPOCH)?.as_secs(),
+            }));
+        }
+
+        let path = dir.join(format_frame_name(pattern, page_index)?);
+
+        page.save_as(&path)?;
+
+        let metadata = json!({
+            "image": get_file_name(&path)?,
+            "page": page_index,
+            "columns": columns,
+            "rows": rows,
+            "tiles": tiles,
+        });
+
+        std::fs::write(
+            path.with_extension("json"),
+            serde_json::to_string_pretty(&metadata)?,
+        )?;
+
+        export.pages.push(path);
+    }
+
+    Ok(export)
+}
+
+fn sort_entries(entries: &mut [SheetEntry], sort_key: ContactSheetSortKey, descending: bool) {
+    let compare = |a: &SheetEntry, b: &SheetEntry| match sort_key {
+        ContactSheetSortKey::None => Ordering::Equal,
+        ContactSheetSortKey::Author => a.author.to_lowercase().cmp(&b.author.to_lowercase()),
+        ContactSheetSortKey::Date => a.timestamp.cmp(&b.timestamp),
+        ContactSheetSortKey::FrameCount => a.frame_count.cmp(&b.frame_count),
+        ContactSheetSortKey::Duration => a.duration.total_cmp(&b.duration),
+        ContactSheetSortKey::FileName => a.file_name.cmp(&b.file_name),
+    };
+
+    //stable, and descending flips the comparison rather than the result, so flipnotes that compare equal keep the order they were given in.
+    match descending {
+        true => entries.sort_by(|a, b| compare(b, a)),
+        false => entries.sort_by(compare),
+    }
+}
+
+fn get_picture(file: &PPMFile, options: &ContactSheetOptions) -> Result<ImageWrapper> {
+    let index = match options.tile {
+        ContactSheetTile::Thumbnail => {
+            return Ok(file
+                .thumbnail
+                .get_image_with(&options.palette)?
+                .scale_nearest(options.tile_scale));
+        }
+        ContactSheetTile::ThumbnailFrame => file.get_thumbnail_frame_index(),
+        ContactSheetTile::Frame(index) => index,
+    };
+
+    let frames = file.animation_data.get_frames()?;
+
+    ensure!(!frames.is_empty(), "The flipnote has no frames");
+
+    let frame = &frames[index.min(frames.len() - 1)];
+
+    //frames are 4 times the size of thumbnails.
+    match options.tile_scale.is_multiple_of(4) {
+        true => frame.get_image_with(&RenderOptions {
+            scale: options.tile_scale / 4,
+            palette: options.palette,
+            ..Default::default()
+        }),
+        false => frame
+            .get_image_with(&RenderOptions {
+                palette: options.palette,
+                ..Default::default()
+            })?
+            .resize_with_mode(
+                64 * options.tile_scale,
+                48 * options.tile_scale,
+                ResizeMode::Stretch,
+                &options.background,
+            ),
+    }
+}
+
+/// Formats a time as `YYYY-MM-DD HH:MM`. Flipnotes store the local time of the console without a time zone, so none is applied.
+fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let (days, seconds_of_day) = ((seconds / 86400) as i64, seconds % 86400);
+
+    //days to a civil date, from Howard Hinnant's date algorithms.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = match month_index < 10 {
+        true => month_index + 3,
+        false => month_index - 9,
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::utils::test_utils::get_temp_dir;
+
+    fn get_entry(author: &str, frame_count: usize) -> SheetEntry {
+        SheetEntry {
+            path: PathBuf::from(format!("{author}-{frame_count}.ppm")),
+            picture: ImageWrapper::new(1, 1),
+            author: author.to_string(),
+            file_name: String::new(),
+            frame_count,
+            duration: frame_count as f32 / 12.0,
+            timestamp: UNIX_EPOCH,
+        }
+    }
+
+    fn get_order(entries: &[SheetEntry]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|entry| entry.path.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn sorting_keeps_equal_flipnotes_in_order_both_ways() {
+        let mut entries = vec![
+            get_entry("b", 10),
+            get_entry("A", 20),
+            get_entry("c", 10),
+            get_entry("a", 30),
+        ];
+
+        sort_entries(&mut entries, ContactSheetSortKey::Author, false);
+        assert_eq!(
+            get_order(&entries),
+            ["A-20.ppm", "a-30.ppm", "b-10.ppm", "c-10.ppm"]
+        );
+
+        sort_entries(&mut entries, ContactSheetSortKey::Author, true);
+        assert_eq!(
+            get_order(&entries),
+            ["c-10.ppm", "b-10.ppm", "A-20.ppm", "a-30.ppm"]
+        );
+
+        sort_entries(&mut entries, ContactSheetSortKey::FrameCount, true);
+        assert_eq!(
+            get_order(&entries),
+            ["a-30.ppm", "A-20.ppm", "c-10.ppm", "b-10.ppm"]
+        );
+
+        sort_entries(&mut entries, ContactSheetSortKey::Duration, false);
+        assert_eq!(
+            get_order(&entries),
+            ["c-10.ppm", "b-10.ppm", "A-20.ppm", "a-30.ppm"]
+        );
+
+        //nothing compares as different, so descending keeps the order too.
+        sort_entries(&mut entries, ContactSheetSortKey::Date, true);
+        assert_eq!(
+            get_order(&entries),
+            ["c-10.ppm", "b-10.ppm", "A-20.ppm", "a-30.ppm"]
+        );
+    }
+
+    #[test]
+    fn timestamps_format_as_dates() {
+        let format = |seconds: u64| format_timestamp(UNIX_EPOCH + Duration::from_secs(seconds));
+
+        assert_eq!(format(0), "1970-01-01 00:00");
+        //the flipnote epoch, and a leap day.
+        assert_eq!(format(946684800), "2000-01-01 00:00");
+        assert_eq!(format(951827696), "2000-02-29 12:34");
+        assert_eq!(format(1300000000), "2011-03-13 07:06");
+        assert_eq!(format(1704067199), "2023-12-31 23:59");
+    }
+
+    #[test]
+    fn unreadable_files_are_skipped() {
+        let dir = get_temp_dir("contact-sheet-skipped");
+        let broken = dir.join("broken.ppm");
+
+        std::fs::write(&broken, b"PARA not a flipnote").unwrap();
+
+        let paths = [
+            PathBuf::from(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../example/flipnotes/mrjohn.ppm"
+            )),
+            broken.clone(),
+        ];
+
+        let export = export_contact_sheet(
+            &paths,
+            dir.join("sheets"),
+            "sheet_{}.png",
+            &ContactSheetOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(export.pages, [dir.join("sheets").join("sheet_0.png")]);
+        assert_eq!(export.skipped, [broken]);
+
+        let page = ImageWrapper::load(&export.pages[0]).unwrap();
+
+        assert_eq!(
+            (page.get_width(), page.get_height()),
+            (
+                64 + 2 * TILE_PADDING,
+                48 + 3 * TILE_PADDING + CAPTION_LINES * (GLYPH_HEIGHT + 2)
+            )
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,320 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail, ensure};
+use serde_json::json;
+
+use crate::{
+    ppm::{
+        file::PPMFile,
+        frames::{
+            debug_render::{DebugRenderOptions, get_debug_image},
+            onion_skin::{OnionSkinOptions, OnionSkinRenderer},
+            render_options::RenderOptions,
+        },
+    },
+    utils::image_utils::ImageWrapper,
+};
+
+/// Options for [`export_sprite_sheet`].
+#[derive(Debug, Clone, Default)]
+pub struct SpriteSheetOptions {
+    /// Frames per row, chosen to make the sheet roughly square if `None`.
+    pub columns: Option<usize>,
+    pub render_options: RenderOptions,
+}
+
+/// Saves every frame as an image in `dir`, returning the written paths in frame order.
+/// `pattern` names the files, with `{}` replaced by the frame index, or `{:0N}` to pad it to N digits, e.g. `frame_{:03}.png`.
+/// The image format follows the extension, formats without alpha drop it from transparent paper.
+pub fn export_frames(
+    file: &PPMFile,
+    dir: impl Into<PathBuf>,
+    pattern: &str,
+    options: &RenderOptions,
+) -> Result<Vec<PathBuf>> {
+    let dir: PathBuf = dir.into();
+
+    options.validate()?;
+
+    std::fs::create_dir_all(&dir)?;
+
+    let frames = file.animation_data.get_frames()?;
+
+    let mut paths = Vec::with_capacity(frames.len());
+
+    for (i, frame) in frames.iter().enumerate() {
+        let path = dir.join(format_frame_name(pattern, i)?);
+
+        frame.get_image_with(options)?.save_as(&path)?;
+
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+/// Saves every frame with onion skinning as an image in `dir`, returning the written paths in frame order.
+/// `pattern` and the image format work like in [`export_frames`].
+pub fn export_onion_skin_frames(
+    file: &PPMFile,
+    dir: impl Into<PathBuf>,
+    pattern: &str,
+    options: &OnionSkinOptions,
+) -> Result<Vec<PathBuf>> {
+    let dir: PathBuf = dir.into();
+
+    std::fs::create_dir_all(&dir)?;
+
+    let frames = file.animation_data.get_frames()?;
+    let mut renderer = OnionSkinRenderer::new(&frames, options.clone())?;
+
+    let mut paths = Vec::with_capacity(frames.len());
+
+    for i in 0..frames.len() {
+        let path = dir.join(format_frame_name(pattern, i)?);
+
+        renderer.get_image(i)?.save_as(&path)?;
+
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+/// Saves the debug image of every frame in `dir`, returning the written paths in frame order.
+/// `pattern` and the image format work like in [`export_frames`].
+pub fn export_debug_frames(
+    file: &PPMFile,
+    dir: impl Into<PathBuf>,
+    pattern: &str,
+    options: &DebugRenderOptions,
+) -> Result<Vec<PathBuf>> {
+    let dir: PathBuf = dir.into();
+
+    options.validate()?;
+
+    std::fs::create_dir_all(&dir)?;
+
+    let frames = file.animation_data.get_frames()?;
+
+    let mut paths = Vec::with_capacity(frames.len());
+
+    for (i, frame) in frames.iter().enumerate() {
+        let path = dir.join(format_frame_name(pattern, i)?);
+        let previous = i.checked_sub(1).map(|previous| &frames[previous]);
+
+        get_debug_image(frame, previous, options)?.save_as(&path)?;
+
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+/// Packs every frame into one image, left to right and top to bottom, and writes a JSON file next to it with the same name.
+/// The JSON lists the rect of each frame along with its sound effect flags, and the framerate and loop flag of the flipnote.
+pub fn export_sprite_sheet(
+    file: &PPMFile,
+    path: impl Into<PathBuf>,
+    options: &SpriteSheetOptions,
+) -> Result<()> {
+    let path: PathBuf = path.into();
+    let render_options = &options.render_options;
+
+    render_options.validate()?;
+
+    let frames = file.animation_data.get_frames()?;
+
+    let frame_width = render_options.get_width();
+    let frame_height = render_options.get_height();
+
+    let columns = options
+        .columns
+        .unwrap_or_else(|| (frames.len() as f32).sqrt().ceil() as usize)
+        .clamp(1, frames.len());
+    let rows = frames.len().div_ceil(columns);
+
+    let mut sheet = ImageWrapper::new(columns as u32 * frame_width, rows as u32 * frame_height);
+    let mut frame_rects = Vec::with_capacity(frames.len());
+
+    for (i, frame) in frames.iter().enumerate() {
+        let x = (i % columns) as u32 * frame_width;
+        let y = (i / columns) as u32 * frame_height;
+
+        sheet.paste(&frame.get_image_with(render_options)?, x, y);
+
+        frame_rects.push(json!({
+            "index": i,
+            "x": x,
+            "y": y,
+            "width": frame_width,
+            "height": frame_height,
+            "sound_effect_flags": file.audio.audio_header.sound_effect_flags.get(i).copied().unwrap_or(0),
+        }));
+    }
+
+    sheet.save_as(&path)?;
+
+    let metadata = json!({
+        "image": get_file_name(&path)?,
+        "frame_width": frame_width,
+        "frame_height": frame_height,
+        "columns": columns,
+        "rows": rows,
+        "frame_count": frames.len(),
+        "fps": file.audio.audio_header.get_framerate()?,
+        "loop": file.animation_data.get_animation_flags().get_loop(),
+        "frames": frame_rects,
+    });
+
+    std::fs::write(
+        path.with_extension("json"),
+        serde_json::to_string_pretty(&metadata)?,
+    )?;
+
+    Ok(())
+}
+
+/// Replaces the `{}` or `{:0N}` placeholder in `pattern` with the frame index.
+pub(crate) fn format_frame_name(pattern: &str, index: usize) -> Result<String> {
+    let (Some(start), Some(end)) = (pattern.find('{'), pattern.find('}')) else {
+        bail!("The file name pattern needs a {{}} placeholder for the frame index");
+    };
+
+    ensure!(start < end, "Invalid file name pattern {}", pattern);
+
+    let number = match &pattern[start + 1..end] {
+        "" => index.to_string(),
+        spec if spec.starts_with(":0") => {
+            let width = spec[2..]
+                .parse::<usize>()
+                .map_err(|_| anyhow::anyhow!("Invalid padding in file name pattern {}", pattern))?;
+
+            format!("{:0width$}", index)
+        }
+        _ => bail!("Invalid file name pattern {}", pattern),
+    };
+
+    Ok(format!(
+        "{}{}{}",
+        &pattern[..start],
+        number,
+        &pattern[end + 1..]
+    ))
+}
+
+pub(crate) fn get_file_name(path: &Path) -> Result<String> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{} is not a file path", path.display()))?;
+
+    Ok(file_name.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        ppm::file_builder::PPMFileBuilder,
+        utils::test_utils::{get_temp_dir, get_test_frame},
+    };
+
+    #[test]
+    fn frame_names_fill_in_the_index() {
+        assert_eq!(format_frame_name("frame_{}.png", 7).unwrap(), "frame_7.png");
+        assert_eq!(format_frame_name("{:03}.bmp", 7).unwrap(), "007.bmp");
+        assert_eq!(format_frame_name("{:02}", 123).unwrap(), "123");
+
+        for pattern in [
+            "frame.png",
+            "frame_{.png",
+            "}frame{",
+            "{:3}.png",
+            "{:0x}.png",
+            "{x}.png",
+        ] {
+            assert!(format_frame_name(pattern, 0).is_err(), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn sprite_sheet_has_a_rect_per_frame() {
+        let dir = get_temp_dir("sprite-sheet");
+        let path = dir.join("sheet.png");
+
+        //12 fps.
+        let file = PPMFileBuilder::new()
+            .frames([0, 1, 0, 1, 1].map(get_test_frame).to_vec())
+            .speed(6)
+            .loop_animation(true)
+            .sound_effect_flags(vec![0, 0b101, 0, 0, 0b010])
+            .seed(1)
+            .build()
+            .unwrap();
+
+        export_sprite_sheet(&file, &path, &SpriteSheetOptions::default()).unwrap();
+
+        //5 frames make a 3x2 sheet.
+        let sheet = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(sheet.dimensions(), (768, 384));
+
+        //(17, 2) is paper, frame 4 is on black paper in the second row.
+        assert_eq!(sheet.get_pixel(256 + 17, 192 + 2).0, [14, 14, 14, 255]);
+        assert_eq!(sheet.get_pixel(512 + 17, 192 + 2).0, [0, 0, 0, 0]);
+
+        let metadata: Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join("sheet.json")).unwrap())
+                .unwrap();
+
+        assert_eq!(metadata["image"], "sheet.png");
+        assert_eq!(metadata["columns"], 3);
+        assert_eq!(metadata["rows"], 2);
+        assert_eq!(metadata["frame_count"], 5);
+        assert_eq!(metadata["fps"], 12.0);
+        assert_eq!(metadata["loop"], true);
+
+        let rects = metadata["frames"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|rect| {
+                (
+                    rect["x"].as_u64().unwrap(),
+                    rect["y"].as_u64().unwrap(),
+                    rect["sound_effect_flags"].as_u64().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            rects,
+            [
+                (0, 0, 0),
+                (256, 0, 0b101),
+                (512, 0, 0),
+                (0, 192, 0),
+                (256, 192, 0b010)
+            ]
+        );
+        assert!(
+            metadata["frames"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .all(|rect| rect["width"] == 256 && rect["height"] == 192)
+        );
+
+        let options = SpriteSheetOptions {
+            columns: Some(10),
+            ..Default::default()
+        };
+        export_sprite_sheet(&file, &path, &options).unwrap();
+
+        //more columns than frames make a single row.
+        assert_eq!(image::image_dimensions(&path).unwrap(), (1280, 192));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,136 @@
+use std::io::Write;
+
+use anyhow::Result;
+use gif::{DisposalMethod, Encoder, Frame, Repeat};
+
+use crate::ppm::file::PPMFile;
+
+use super::{AnimationExportOptions, get_frame_runs, get_frame_time};
+
+/// Writes the flipnote as an animated GIF, each frame using its paper and layer colors as a 4 color palette.
+/// Runs of identical frames are merged into one longer frame. Delays are rounded to 1/100s against the running time, so slow speeds like 0.5 fps and fast ones like 30 fps keep their total length.
+pub fn write_gif(
+    file: &PPMFile,
+    writer: impl Write,
+    options: &AnimationExportOptions,
+) -> Result<()> {
+    let render_options = &options.render_options;
+    render_options.validate()?;
+
+    let frames = file.animation_data.get_frames()?;
+    let framerate = file.audio.audio_header.get_framerate()?;
+
+    let width = render_options.get_width() as u16;
+    let height = render_options.get_height() as u16;
+
+    let mut encoder = Encoder::new(writer, width, height, &[])?;
+
+    //GIFs without a loop extension play once.
+    if file.animation_data.get_animation_flags().get_loop() {
+        encoder.set_repeat(Repeat::Infinite)?;
+    }
+
+    for run in get_frame_runs(&frames, render_options)? {
+        let frame = &frames[run.frame_index];
+
+        let [paper, layer_1, layer_2] = frame.get_palette_with(render_options)?;
+        //GIF palettes hold a power of two colors, the 4th entry is never used.
+        let palette = [paper, layer_1, layer_2, paper]
+            .iter()
+            .flat_map(|color| [color.r, color.g, color.b])
+            .collect::<Vec<u8>>();
+
+        let transparent = render_options.get_transparent_paper().then_some(0);
+
+        let mut gif_frame = Frame::from_palette_pixels(
+            width,
+            height,
+            frame.get_indexed_pixels_with(render_options)?,
+            palette,
+            transparent,
+        );
+
+        //transparent pixels would show the previous frame unless it is cleared first.
+        if transparent.is_some() {
+            gif_frame.dispose = DisposalMethod::Background;
+        }
+
+        let mut delay = get_frame_time(run.frame_index + run.length, framerate, 100.0)
+            - get_frame_time(run.frame_index, framerate, 100.0);
+
+        //delays above u16::MAX are written as the same picture repeated.
+        while delay > 0 {
+            gif_frame.delay = delay.min(u16::MAX as u32) as u16;
+            delay -= gif_frame.delay as u32;
+
+            encoder.write_frame(&gif_frame)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use gif::DecodeOptions;
+
+    use super::*;
+    use crate::{ppm::frames::render_options::PaperMode, utils::test_utils::get_test_file};
+
+    /// Returns the loop setting and every frame of the written GIF.
+    fn write(file: &PPMFile, options: &AnimationExportOptions) -> (Repeat, Vec<Frame<'static>>) {
+        let mut data = vec![];
+        write_gif(file, &mut data, options).unwrap();
+
+        let mut decoder = DecodeOptions::new().read_info(data.as_slice()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (256, 192));
+
+        let mut frames = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push(frame.clone());
+        }
+
+        (decoder.repeat(), frames)
+    }
+
+    #[test]
+    fn gif_delays_add_up_to_the_running_time() {
+        //30 fps, the first picture shows for 2 frames.
+        let file = get_test_file(&[0, 0, 1, 2], 8);
+        let (repeat, frames) = write(&file, &AnimationExportOptions::default());
+
+        assert_eq!(repeat, Repeat::Finite(0));
+
+        //white paper, red and blue layers.
+        assert_eq!(
+            frames[0].palette.as_deref().unwrap(),
+            [255, 255, 255, 255, 42, 42, 10, 57, 255, 255, 255, 255]
+        );
+        assert_eq!(frames[0].transparent, None);
+
+        //7, 10 and 13.33 hundredths of a second from the start.
+        let delays = frames.iter().map(|frame| frame.delay).collect::<Vec<_>>();
+        assert_eq!(delays, [7, 3, 3]);
+    }
+
+    #[test]
+    fn gif_loops_and_clears_transparent_paper() {
+        let mut file = get_test_file(&[0, 1], 1);
+        file.animation_data.get_animation_flags_mut().set_loop(true);
+
+        let mut options = AnimationExportOptions::default();
+        options.render_options.paper = PaperMode::Transparent;
+
+        let (repeat, frames) = write(&file, &options);
+
+        assert_eq!(repeat, Repeat::Infinite);
+        assert_eq!(frames.len(), 2);
+
+        for frame in frames {
+            //0.5 fps.
+            assert_eq!(frame.delay, 200);
+            assert_eq!(frame.transparent, Some(0));
+            assert_eq!(frame.dispose, DisposalMethod::Background);
+        }
+    }
+}
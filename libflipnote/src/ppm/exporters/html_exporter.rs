@@ -0,0 +1,171 @@
+use std::{io::Write, time::UNIX_EPOCH};
+
+use anyhow::Result;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use flate2::{Compression, write::ZlibEncoder};
+use serde_json::json;
+
+use crate::{
+    ppm::{
+        file::PPMFile,
+        frames::{
+            render_options::RenderOptions,
+            render_sink::{BitOrder, BitplaneContent, BitplaneSink},
+        },
+    },
+    utils::color_utils::rgb_to_hex,
+};
+
+const HTML_PLAYER_TEMPLATE: &str = include_str!("html_player.html");
+
+/// Writes a self-contained web page that plays the flipnote, with play/pause, frame stepping, loop, and the speed, author and date shown below.
+/// Frames are stored as two 1-bit layers plus their colors and compressed, so the page stays small. The mixed audio is embedded as a WAV file.
+/// The frames are rendered with `options` up front, so the page shows them at that size, upscaler, layer visibility and paper.
+pub fn write_html(file: &PPMFile, mut writer: impl Write, options: &RenderOptions) -> Result<()> {
+    options.validate()?;
+
+    let frames = file.animation_data.get_frames()?;
+
+    let mut layers = ZlibEncoder::new(Vec::new(), Compression::best());
+    let mut palettes = Vec::with_capacity(frames.len());
+
+    for frame in frames.iter() {
+        //the player draws layer 1 over layer 2, so only the visible pixels are stored.
+        let mut sink = BitplaneSink::new(BitplaneContent::Visible, BitOrder::LsbFirst);
+        frame.render_to(&mut sink, options)?;
+
+        for plane in sink.into_planes() {
+            layers.write_all(&plane)?;
+        }
+
+        palettes.push(
+            frame
+                .get_palette_with(options)?
+                .iter()
+                .map(rgb_to_hex)
+                .collect::<Vec<String>>(),
+        );
+    }
+
+    let audio = match &file.audio.mixed_tracks {
+        Some(track) => Some(STANDARD.encode(track.get_wav_buffer()?)),
+        None => None,
+    };
+
+    let data = json!({
+        "width": options.get_width(),
+        "height": options.get_height(),
+        "transparentPaper": options.get_transparent_paper(),
+        "frameCount": frames.len(),
+        "speed": file.audio.audio_header.get_speed(),
+        "fps": file.audio.audio_header.get_framerate()?,
+        "loop": file.animation_data.get_animation_flags().get_loop(),
+        "author": file.get_current_author_name(),
+        "timestamp": file.get_timestamp().duration_since(UNIX_EPOCH)?.as_secs(),
+        "palettes": palettes,
+        "layers": STANDARD.encode(layers.finish()?),
+        "audio": audio,
+    });
+
+    //keeps author names from closing the script tag.
+    let data = serde_json::to_string(&data)?.replace("</", "<\\/");
+
+    writer.write_all(HTML_PLAYER_TEMPLATE.replace("{{data}}", &data).as_bytes())?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::ZlibDecoder;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{ppm::file_builder::PPMFileBuilder, utils::test_utils::get_test_frame};
+
+    /// Writes the page and returns the embedded data, checking the template around it is untouched.
+    fn get_data(file: &PPMFile, options: &RenderOptions) -> (String, Value) {
+        let mut page = vec![];
+        write_html(file, &mut page, options).unwrap();
+
+        let page = String::from_utf8(page).unwrap();
+        let (prefix, suffix) = HTML_PLAYER_TEMPLATE.split_once("{{data}}").unwrap();
+
+        assert!(page.starts_with(prefix) && page.ends_with(suffix));
+
+        let data = page[prefix.len()..page.len() - suffix.len()].to_string();
+        let value = serde_json::from_str(&data).unwrap();
+
+        (data, value)
+    }
+
+    fn get_layers(data: &Value) -> Vec<u8> {
+        let compressed = STANDARD.decode(data["layers"].as_str().unwrap()).unwrap();
+
+        let mut layers = vec![];
+        ZlibDecoder::new(compressed.as_slice())
+            .read_to_end(&mut layers)
+            .unwrap();
+
+        layers
+    }
+
+    #[test]
+    fn author_names_cannot_close_the_script() {
+        let file = PPMFileBuilder::new()
+            .author_name("</script>")
+            .frames(vec![get_test_frame(0)])
+            .seed(1)
+            .build()
+            .unwrap();
+
+        let (raw, data) = get_data(&file, &RenderOptions::default());
+
+        assert!(!raw.contains("</"));
+        assert_eq!(data["author"], "</script>");
+    }
+
+    #[test]
+    fn every_frame_stores_two_bitplanes() {
+        let file = PPMFileBuilder::new()
+            .frames((0..3).map(get_test_frame).collect())
+            .seed(1)
+            .build()
+            .unwrap();
+
+        for scale in [1, 2] {
+            let options = RenderOptions {
+                scale,
+                ..Default::default()
+            };
+
+            let (_, data) = get_data(&file, &options);
+
+            assert_eq!(data["frameCount"], 3);
+            assert_eq!(data["width"], 256 * scale);
+            assert_eq!(data["palettes"].as_array().unwrap().len(), 3);
+
+            //2 layers of 1 bit per pixel for every frame.
+            let plane_size = (256 * 192 * scale * scale / 8) as usize;
+            let layers = get_layers(&data);
+
+            assert_eq!(layers.len(), 3 * 2 * plane_size);
+
+            //each frame's layers follow each other, in the same bit order as the player reads them.
+            let frames = file.animation_data.get_frames().unwrap();
+
+            for (i, frame) in frames.iter().enumerate() {
+                let mut sink = BitplaneSink::new(BitplaneContent::Visible, BitOrder::LsbFirst);
+                frame.render_to(&mut sink, &options).unwrap();
+
+                assert_eq!(
+                    layers[i * 2 * plane_size..(i + 1) * 2 * plane_size],
+                    sink.into_planes().concat()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
+use png::{BitDepth, ColorType, Encoder};
+
+use crate::{
+    ppm::{
+        file::PPMFile,
+        frames::{
+            frame::PPMFrame,
+            render_options::RenderOptions,
+            render_sink::{
+                BitOrder, BitplaneContent, BitplaneSink, RenderInfo, RenderRow, RenderSink,
+            },
+        },
+    },
+    utils::image_utils::ImageWrapper,
+};
+
+use super::frame_exporter::format_frame_name;
+
+/// How [`export_layers`] writes each layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LayerExportMode {
+    /// RGBA images with the ink in the layer's color on a transparent background.
+    #[default]
+    Color,
+    /// 1-bit masks of the ink, as PNG or PBM depending on the extension.
+    Mask,
+}
+
+/// Options for [`export_layers`].
+#[derive(Debug, Clone, Default)]
+pub struct LayerExportOptions {
+    pub mode: LayerExportMode,
+    /// Size, upscaler, layer visibility and colors of the layers. The paper is always left out.
+    pub render_options: RenderOptions,
+}
+
+/// Returns layer 1 and layer 2 as RGBA images, each with all of its own ink in its resolved color and everything else transparent.
+/// Ink of layer 2 that layer 1 covers is kept, so the two can be composited over other content.
+pub fn get_layer_images(frame: &PPMFrame, options: &RenderOptions) -> Result<[ImageWrapper; 2]> {
+    let mut sink = LayerSink::default();
+
+    frame.render_to(&mut sink, options)?;
+
+    let [layer_1, layer_2] = sink.pixels;
+
+    Ok([
+        ImageWrapper::from_raw_pixels(options.get_width(), options.get_height(), layer_1)?,
+        ImageWrapper::from_raw_pixels(options.get_width(), options.get_height(), layer_2)?,
+    ])
+}
+
+/// Returns layer 1 and layer 2 as 1 bit per pixel masks of their ink, rows packed with the leftmost pixel in the highest bit.
+pub fn get_layer_masks(frame: &PPMFrame, options: &RenderOptions) -> Result<[Vec<u8>; 2]> {
+    let mut sink = BitplaneSink::new(BitplaneContent::Ink, BitOrder::MsbFirst);
+
+    frame.render_to(&mut sink, options)?;
+
+    Ok(sink.into_planes())
+}
+
+/// Saves every frame as two images, one per layer, in the `layer_1` and `layer_2` folders of `dir`, returning the written paths of each layer in frame order.
+/// `pattern` names the files like in [`export_frames`](super::frame_exporter::export_frames). Colored layers can be saved in any format with alpha,
+/// masks as `.png` (ink is white) or `.pbm` (ink is black).
+pub fn export_layers(
+    file: &PPMFile,
+    dir: impl Into<PathBuf>,
+    pattern: &str,
+    options: &LayerExportOptions,
+) -> Result<[Vec<PathBuf>; 2]> {
+    let dir: PathBuf = dir.into();
+    let render_options = &options.render_options;
+
+    render_options.validate()?;
+
+    let layer_dirs = [dir.join("layer_1"), dir.join("layer_2")];
+
+    for layer_dir in layer_dirs.iter() {
+        std::fs::create_dir_all(layer_dir)?;
+    }
+
+    let frames = file.animation_data.get_frames()?;
+
+    let mut paths = [
+        Vec::with_capacity(frames.len()),
+        Vec::with_capacity(frames.len()),
+    ];
+
+    for (i, frame) in frames.iter().enumerate() {
+        let name = format_frame_name(pattern, i)?;
+        let layer_paths = layer_dirs.each_ref().map(|layer_dir| layer_dir.join(&name));
+
+        match options.mode {
+            LayerExportMode::Color => {
+                for (image, path) in get_layer_images(frame, render_options)?
+                    .iter()
+                    .zip(layer_paths.iter())
+                {
+                    image.save_as(path)?;
+                }
+            }
+            LayerExportMode::Mask => {
+                for (mask, path) in get_layer_masks(frame, render_options)?
+                    .iter()
+                    .zip(layer_paths.iter())
+                {
+                    write_mask(
+                        path,
+                        mask,
+                        render_options.get_width(),
+                        render_options.get_height(),
+                    )?;
+                }
+            }
+        }
+
+        for (layer_paths_list, path) in paths.iter_mut().zip(layer_paths) {
+            layer_paths_list.push(path);
+        }
+    }
+
+    Ok(paths)
+}
+
+fn write_mask(path: &Path, mask: &[u8], width: u32, height: u32) -> Result<()> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    match extension.as_deref() {
+        Some("png") => {
+            let mut encoder = Encoder::new(&mut writer, width, height);
+            encoder.set_color(ColorType::Grayscale);
+            encoder.set_depth(BitDepth::One);
+
+            let mut png_writer = encoder.write_header()?;
+            png_writer.write_image_data(mask)?;
+            png_writer.finish()?;
+        }
+        Some("pbm") => {
+            write!(writer, "P4\n{} {}\n", width, height)?;
+            writer.write_all(mask)?;
+        }
+        _ => bail!(
+            "Masks can only be saved as .png or .pbm, not {}",
+            path.display()
+        ),
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Collects the ink of each layer as RGBA in the layer's color.
+#[derive(Default)]
+struct LayerSink {
+    colors: [[u8; 3]; 2],
+    pixels: [Vec<u8>; 2],
+}
+
+impl RenderSink for LayerSink {
+    fn begin(&mut self, info: &RenderInfo) -> Result<()> {
+        let size = (info.width * info.height * 4) as usize;
+
+        self.colors = [1, 2].map(|index| {
+            let color = info.colors[index];
+
+            [color.r, color.g, color.b]
+        });
+        self.pixels = [Vec::with_capacity(size), Vec::with_capacity(size)];
+
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &RenderRow) -> Result<()> {
+        for (layer, pixels) in self.pixels.iter_mut().enumerate() {
+            let [r, g, b] = self.colors[layer];
+
+            for ink in row.layer_ink[layer] {
+                match ink {
+                    1 => pixels.extend_from_slice(&[r, g, b, 255]),
+                    _ => pixels.extend_from_slice(&[0, 0, 0, 0]),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn needs_layer_ink(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ppm::{
+            file_builder::PPMFileBuilder,
+            frames::frame_header::{PPMLayerColor, PPMPaperColor},
+        },
+        utils::test_utils::get_temp_dir,
+    };
+
+    /// White paper with red ink at (9, 1) and blue ink under it and at (10, 1).
+    fn get_frame() -> PPMFrame {
+        let mut frame = PPMFrame::default();
+
+        let header = frame.get_header_mut();
+        header.set_paper_color(PPMPaperColor::White);
+        header.set_layer_color(1, PPMLayerColor::Red).unwrap();
+        header.set_layer_color(2, PPMLayerColor::Blue).unwrap();
+
+        frame.get_layer_mut(1).unwrap().set(9, 1, true).unwrap();
+        frame.get_layer_mut(2).unwrap().set(9, 1, true).unwrap();
+        frame.get_layer_mut(2).unwrap().set(10, 1, true).unwrap();
+
+        frame
+    }
+
+    #[test]
+    fn masks_have_a_bit_per_pixel() {
+        let dir = get_temp_dir("layer-masks");
+
+        let file = PPMFileBuilder::new()
+            .frames(vec![get_frame()])
+            .seed(1)
+            .build()
+            .unwrap();
+
+        let options = LayerExportOptions {
+            mode: LayerExportMode::Mask,
+            ..Default::default()
+        };
+
+        let [layer_1, layer_2] = export_layers(&file, &dir, "{}.pbm", &options).unwrap();
+
+        assert_eq!(layer_1, [dir.join("layer_1").join("0.pbm")]);
+
+        let header = b"P4\n256 192\n";
+        //x 9 of row 1 is the 2nd highest bit of byte 33, the ink under layer 1 is kept.
+        for (path, byte) in [(&layer_1[0], 0x40), (&layer_2[0], 0x60)] {
+            let pbm = std::fs::read(path).unwrap();
+
+            assert_eq!(pbm.len(), header.len() + 256 * 192 / 8);
+            assert_eq!(&pbm[..header.len()], header);
+
+            let mask = &pbm[header.len()..];
+            assert_eq!(mask[33], byte);
+            assert_eq!(mask.iter().filter(|byte| **byte != 0).count(), 1);
+        }
+
+        //PNG masks are white where there is ink.
+        let [layer_1, _] = export_layers(&file, &dir, "{}.png", &options).unwrap();
+        let mask = image::open(&layer_1[0]).unwrap().to_luma8();
+
+        assert_eq!(mask.dimensions(), (256, 192));
+        assert_eq!(mask.get_pixel(9, 1).0, [255]);
+        assert_eq!(mask.get_pixel(10, 1).0, [0]);
+
+        assert!(export_layers(&file, &dir, "{}.bmp", &options).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn layer_images_keep_covered_ink() {
+        let [layer_1, layer_2] = get_layer_images(&get_frame(), &RenderOptions::default()).unwrap();
+
+        let [layer_1, layer_2] = [layer_1, layer_2].map(|image| image.get_raw_pixels());
+        let get_pixel =
+            |pixels: &[u8], x: usize, y: usize| pixels[(y * 256 + x) * 4..][..4].to_vec();
+
+        assert_eq!(get_pixel(&layer_1, 9, 1), [255, 42, 42, 255]);
+        assert_eq!(get_pixel(&layer_1, 10, 1), [0, 0, 0, 0]);
+        assert_eq!(get_pixel(&layer_2, 9, 1), [10, 57, 255, 255]);
+        assert_eq!(get_pixel(&layer_2, 10, 1), [10, 57, 255, 255]);
+
+        //the paper is always transparent.
+        assert_eq!(get_pixel(&layer_2, 0, 0), [0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,149 @@
+//! Exporters that turn flipnotes into other formats without external tools.
+
+use std::io::Write;
+
+use anyhow::Result;
+
+use super::{
+    file::PPMFile,
+    frames::{frame::PPMFrame, render_options::RenderOptions},
+};
+
+pub mod apng_exporter;
+pub mod contact_sheet_exporter;
+pub mod frame_exporter;
+pub mod gif_exporter;
+pub mod html_exporter;
+pub mod layer_exporter;
+pub mod raw_exporter;
+pub mod svg_exporter;
+pub mod terminal_exporter;
+pub mod video_exporter;
+pub mod webp_exporter;
+
+/// The animated image formats [`write_animation`] can produce.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AnimationExportFormat {
+    #[default]
+    Gif,
+    /// Lossless, with full alpha.
+    Apng,
+    /// Lossless animated WebP, with full alpha.
+    WebP,
+}
+
+impl AnimationExportFormat {
+    pub fn get_extension(&self) -> &'static str {
+        match self {
+            AnimationExportFormat::Gif => "gif",
+            AnimationExportFormat::Apng => "png",
+            AnimationExportFormat::WebP => "webp",
+        }
+    }
+}
+
+/// Options shared by the animation exporters.
+#[derive(Debug, Clone, Default)]
+pub struct AnimationExportOptions {
+    /// Size, upscaler, layer visibility and paper of the frames. Transparent paper makes the animation transparent too.
+    pub render_options: RenderOptions,
+}
+
+/// Writes the flipnote as an animation in the given format, at the speed and loop setting from its header.
+pub fn write_animation(
+    file: &PPMFile,
+    writer: impl Write,
+    format: AnimationExportFormat,
+    options: &AnimationExportOptions,
+) -> Result<()> {
+    match format {
+        AnimationExportFormat::Gif => gif_exporter::write_gif(file, writer, options),
+        AnimationExportFormat::Apng => apng_exporter::write_apng(file, writer, options),
+        AnimationExportFormat::WebP => webp_exporter::write_webp(file, writer, options),
+    }
+}
+
+/// A picture that stays on screen for one or more frames.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FrameRun {
+    pub frame_index: usize,
+    pub length: usize,
+}
+
+/// Groups consecutive frames that look the same once rendered, so each picture is stored once with a longer delay.
+pub(crate) fn get_frame_runs(
+    frames: &[PPMFrame],
+    options: &RenderOptions,
+) -> Result<Vec<FrameRun>> {
+    //upscaling only depends on the colors, so comparing at 256x192 gives the same runs.
+    let options = options.unscaled();
+
+    let mut runs: Vec<FrameRun> = Vec::new();
+    let mut last_pixels = Vec::new();
+
+    for (frame_index, frame) in frames.iter().enumerate() {
+        //frames are compared by their colors, the same picture can come from different layer colors.
+        let pixels = frame.get_image_with(&options)?.get_raw_pixels();
+
+        match runs.last_mut() {
+            Some(run) if pixels == last_pixels => run.length += 1,
+            _ => {
+                runs.push(FrameRun {
+                    frame_index,
+                    length: 1,
+                });
+                last_pixels = pixels;
+            }
+        }
+    }
+
+    Ok(runs)
+}
+
+/// Returns when a frame starts, rounded to `units_per_second`.
+/// Delays computed from the difference of two start times keep the total length exact, however the rounding falls.
+pub(crate) fn get_frame_time(frame_index: usize, framerate: f32, units_per_second: f32) -> u32 {
+    (frame_index as f32 / framerate * units_per_second).round() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::get_test_frame;
+
+    #[test]
+    fn frame_runs_merge_consecutive_identical_frames() {
+        let frames = [0, 0, 1, 0, 2, 2, 2].map(get_test_frame);
+
+        let runs = get_frame_runs(&frames, &RenderOptions::default())
+            .unwrap()
+            .iter()
+            .map(|run| (run.frame_index, run.length))
+            .collect::<Vec<_>>();
+
+        //the same picture after another one starts a new run.
+        assert_eq!(runs, [(0, 2), (2, 1), (3, 1), (4, 3)]);
+    }
+
+    #[test]
+    fn frame_times_round_against_the_running_time() {
+        let times = (0..=3)
+            .map(|i| get_frame_time(i, 30.0, 1000.0))
+            .collect::<Vec<_>>();
+
+        assert_eq!(times, [0, 33, 67, 100]);
+
+        //delays of 33, 34 and 33 ms still add up to exactly 100.
+        let delays = times
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect::<Vec<_>>();
+
+        assert_eq!(delays, [33, 34, 33]);
+
+        //GIF delays are in hundredths of a second.
+        assert_eq!(get_frame_time(1, 0.5, 100.0), 200);
+        assert_eq!(get_frame_time(3, 12.0, 100.0), 25);
+        assert_eq!(get_frame_time(7, 20.0, 1000.0), 350);
+    }
+}
@@ -0,0 +1,218 @@
+use std::io::Write;
+
+use anyhow::{Result, ensure};
+
+use crate::ppm::{file::PPMFile, frames::render_options::RenderOptions};
+
+/// Writes the animation as a YUV4MPEG2 stream, with the exact framerate in the header (e.g. `F1:2` for 0.5 fps).
+/// Frames are rendered with [`PPMFrame::get_image_with`](crate::ppm::frames::frame::PPMFrame::get_image_with) and stored as full resolution 4:4:4 BT.601, so no colors bleed into each other.
+/// Y4M has no alpha, transparent paper is written in its color.
+pub fn write_y4m(file: &PPMFile, mut writer: impl Write, options: &RenderOptions) -> Result<()> {
+    options.validate()?;
+
+    let frames = file.animation_data.get_frames()?;
+    let (numerator, denominator) = file.audio.audio_header.get_framerate_ratio()?;
+
+    let width = options.get_width() as usize;
+    let height = options.get_height() as usize;
+
+    writeln!(
+        writer,
+        "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+        width, height, numerator, denominator
+    )?;
+
+    let mut planes = vec![0u8; width * height * 3];
+
+    for frame in frames.iter() {
+        let pixels = frame.get_image_with(options)?.get_raw_pixels();
+
+        let (y_plane, chroma_planes) = planes.split_at_mut(width * height);
+        let (u_plane, v_plane) = chroma_planes.split_at_mut(width * height);
+
+        for (i, pixel) in pixels.chunks_exact(4).enumerate() {
+            (y_plane[i], u_plane[i], v_plane[i]) = rgb_to_yuv(pixel[0], pixel[1], pixel[2]);
+        }
+
+        writer.write_all(b"FRAME\n")?;
+        writer.write_all(&planes)?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes the animation as headerless RGBA frames, rendered with [`PPMFrame::get_image_with`](crate::ppm::frames::frame::PPMFrame::get_image_with).
+/// The reader has to be told the format, e.g. `ffmpeg -f rawvideo -pix_fmt rgba -video_size 256x192 -framerate 1/2 -i -`,
+/// with the size from the options and the framerate from [`PPMAudioHeader::get_framerate_ratio`](crate::ppm::audio::audio_header::PPMAudioHeader::get_framerate_ratio).
+pub fn write_rawvideo(
+    file: &PPMFile,
+    mut writer: impl Write,
+    options: &RenderOptions,
+) -> Result<()> {
+    options.validate()?;
+
+    for frame in file.animation_data.get_frames()?.iter() {
+        writer.write_all(&frame.get_image_with(options)?.get_raw_pixels())?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes the mixed audio as signed 16 bit little endian samples, the companion to [`write_y4m`] and [`write_rawvideo`].
+/// The audio is cut or padded with silence to the exact length of the animation, and the mono track is copied into every channel, interleaved.
+/// Flipnotes without audio give silence.
+pub fn write_s16le(
+    file: &PPMFile,
+    mut writer: impl Write,
+    sample_rate: i32,
+    channels: u16,
+) -> Result<()> {
+    ensure!(channels > 0, "There must be at least one channel");
+
+    let samples = get_timeline_audio(file, sample_rate, 1)?;
+
+    let bytes = samples
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes().repeat(channels as usize))
+        .collect::<Vec<u8>>();
+
+    writer.write_all(&bytes)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Returns the mixed audio at `sample_rate`, played `repetitions` times.
+/// Every repetition is padded or cut to the length of the animation, so the audio stays in sync with the frames.
+pub(crate) fn get_timeline_audio(
+    file: &PPMFile,
+    sample_rate: i32,
+    repetitions: u32,
+) -> Result<Vec<i16>> {
+    ensure!(sample_rate > 0, "Audio sample rate must be positive");
+
+    let track = match &file.audio.mixed_tracks {
+        Some(track) => track.resample(sample_rate)?.get_samples(),
+        None => vec![],
+    };
+
+    let framerate = file.audio.audio_header.get_framerate()? as f64;
+    let duration = file.get_frame_count() as f64 / framerate;
+
+    let get_sample =
+        |repetition: u32| (repetition as f64 * duration * sample_rate as f64).round() as usize;
+
+    let mut samples = Vec::with_capacity(get_sample(repetitions));
+
+    for i in 0..repetitions {
+        let length = get_sample(i + 1) - get_sample(i);
+
+        samples.extend(track.iter().take(length));
+        samples.resize(get_sample(i + 1), 0);
+    }
+
+    Ok(samples)
+}
+
+/// Converts to limited range BT.601, the default for Y4M.
+fn rgb_to_yuv(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+
+    (y as u8, u as u8, v as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ppm::audio::wav_container::WavContainer, utils::test_utils::get_test_file};
+
+    #[test]
+    fn y4m_has_a_header_and_a_frame_per_picture() {
+        //0.5 fps, white paper then black paper.
+        let file = get_test_file(&[0, 1], 1);
+
+        let mut y4m = vec![];
+        write_y4m(&file, &mut y4m, &RenderOptions::default()).unwrap();
+
+        let header = b"YUV4MPEG2 W256 H192 F1:2 Ip A1:1 C444\n";
+        let frame_size = b"FRAME\n".len() + 256 * 192 * 3;
+
+        assert_eq!(&y4m[..header.len()], header);
+        assert_eq!(y4m.len(), header.len() + frame_size * 2);
+
+        let frames = y4m[header.len()..].chunks_exact(frame_size);
+
+        //(17, 2) is paper in both frames, white then flipnote black. Y U and V are separate planes.
+        let paper = 2 * 256 + 17;
+
+        for (frame, paper_y) in frames.zip([235, 28]) {
+            assert_eq!(&frame[..6], b"FRAME\n");
+
+            let planes = &frame[6..];
+            assert_eq!(planes[paper], paper_y);
+            assert_eq!(planes[256 * 192 + paper], 128);
+            assert_eq!(planes[256 * 192 * 2 + paper], 128);
+        }
+
+        let options = RenderOptions {
+            scale: 2,
+            ..Default::default()
+        };
+
+        let mut y4m = vec![];
+        write_y4m(&get_test_file(&[0], 8), &mut y4m, &options).unwrap();
+
+        assert!(y4m.starts_with(b"YUV4MPEG2 W512 H384 F30:1 Ip A1:1 C444\nFRAME\n"));
+    }
+
+    #[test]
+    fn rawvideo_is_headerless_rgba() {
+        let file = get_test_file(&[0, 1], 1);
+
+        let mut raw = vec![];
+        write_rawvideo(&file, &mut raw, &RenderOptions::default()).unwrap();
+
+        assert_eq!(raw.len(), 256 * 192 * 4 * 2);
+        //(17, 2) is paper in both frames.
+        let paper = (2 * 256 + 17) * 4;
+
+        assert_eq!(raw[paper..][..4], [255, 255, 255, 255]);
+        assert_eq!(raw[256 * 192 * 4 + paper..][..4], [14, 14, 14, 255]);
+    }
+
+    #[test]
+    fn s16le_is_padded_to_the_animation_and_interleaved() {
+        //2 frames at 30 fps last 533.33 samples at 8 kHz.
+        let mut file = get_test_file(&[0, 1], 8);
+
+        let mut pcm = vec![];
+        write_s16le(&file, &mut pcm, 8000, 2).unwrap();
+
+        assert_eq!(pcm.len(), 533 * 2 * 2);
+        assert!(pcm.iter().all(|&byte| byte == 0));
+
+        file.audio.mixed_tracks = Some(WavContainer::from_samples(vec![1, -2, 3], 1, 8000, 16));
+
+        let mut pcm = vec![];
+        write_s16le(&file, &mut pcm, 8000, 2).unwrap();
+
+        let samples = pcm
+            .chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect::<Vec<_>>();
+
+        assert_eq!(samples.len(), 533 * 2);
+        assert_eq!(samples[..8], [1, 1, -2, -2, 3, 3, 0, 0]);
+
+        assert!(write_s16le(&file, &mut pcm, 8000, 0).is_err());
+        assert!(write_s16le(&file, &mut pcm, 0, 1).is_err());
+    }
+}
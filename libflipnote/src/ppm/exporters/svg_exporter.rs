@@ -0,0 +1,336 @@
+use std::{collections::BTreeMap, fmt::Write as _, io::Write, path::PathBuf};
+
+use anyhow::Result;
+
+use crate::{
+    ppm::{
+        file::PPMFile,
+        frames::{frame::PPMFrame, render_options::RenderOptions},
+    },
+    utils::color_utils::rgb_to_hex,
+};
+
+use super::{frame_exporter::format_frame_name, get_frame_runs, get_frame_time};
+
+/// Returns the frame as an SVG image. The paper is a rectangle, and each layer is one path made of rectangles merged from its pixels, filled with the layer color.
+/// Transparent paper leaves the rectangle out.
+pub fn get_frame_svg(frame: &PPMFrame, options: &RenderOptions) -> Result<String> {
+    options.validate()?;
+
+    let mut svg = get_svg_header(options);
+
+    write_frame_shapes(&mut svg, frame, options)?;
+
+    svg.push_str("</svg>\n");
+
+    Ok(svg)
+}
+
+/// Saves every frame as an SVG file in `dir`, returning the written paths in frame order.
+/// `pattern` works like in [`export_frames`](super::frame_exporter::export_frames), e.g. `frame_{:03}.svg`.
+pub fn export_svg_frames(
+    file: &PPMFile,
+    dir: impl Into<PathBuf>,
+    pattern: &str,
+    options: &RenderOptions,
+) -> Result<Vec<PathBuf>> {
+    let dir: PathBuf = dir.into();
+
+    std::fs::create_dir_all(&dir)?;
+
+    let frames = file.animation_data.get_frames()?;
+
+    let mut paths = Vec::with_capacity(frames.len());
+
+    for (i, frame) in frames.iter().enumerate() {
+        let path = dir.join(format_frame_name(pattern, i)?);
+
+        std::fs::write(&path, get_frame_svg(frame, options)?)?;
+
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+/// Writes the whole flipnote as one animated SVG. Every picture is a group that SMIL shows for its part of the timeline, at the speed and loop setting from the header.
+/// Runs of identical frames are merged into one group.
+pub fn write_animated_svg(
+    file: &PPMFile,
+    mut writer: impl Write,
+    options: &RenderOptions,
+) -> Result<()> {
+    options.validate()?;
+
+    let frames = file.animation_data.get_frames()?;
+    let framerate = file.audio.audio_header.get_framerate()?;
+
+    let duration = get_frame_time(frames.len(), framerate, 1000.0);
+
+    let repeat = match file.animation_data.get_animation_flags().get_loop() {
+        true => "repeatCount=\"indefinite\"",
+        false => "repeatCount=\"1\" fill=\"freeze\"",
+    };
+
+    let runs = get_frame_runs(&frames, options)?;
+    let run_count = runs.len();
+
+    let mut svg = get_svg_header(options);
+
+    for (i, run) in runs.into_iter().enumerate() {
+        let start = get_frame_time(run.frame_index, framerate, 1000.0) as f64 / duration as f64;
+        let end = get_frame_time(run.frame_index + run.length, framerate, 1000.0) as f64
+            / duration as f64;
+
+        //with discrete timing, each value holds from its key time until the next one.
+        let (values, key_times) = match (i == 0, i == run_count - 1) {
+            (true, true) => (String::from("visible"), String::from("0")),
+            (true, false) => (String::from("visible;hidden"), format!("0;{:.6}", end)),
+            (false, true) => (String::from("hidden;visible"), format!("0;{:.6}", start)),
+            (false, false) => (
+                String::from("hidden;visible;hidden"),
+                format!("0;{:.6};{:.6}", start, end),
+            ),
+        };
+
+        let visibility = match i {
+            0 => "visible",
+            _ => "hidden",
+        };
+
+        writeln!(svg, "<g visibility=\"{}\">", visibility)?;
+        writeln!(
+            svg,
+            "<animate attributeName=\"visibility\" values=\"{}\" keyTimes=\"{}\" dur=\"{}ms\" calcMode=\"discrete\" {}/>",
+            values, key_times, duration, repeat
+        )?;
+
+        write_frame_shapes(&mut svg, &frames[run.frame_index], options)?;
+
+        svg.push_str("</g>\n");
+    }
+
+    svg.push_str("</svg>\n");
+
+    writer.write_all(svg.as_bytes())?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn get_svg_header(options: &RenderOptions) -> String {
+    //crisp edges keeps antialiasing from leaving seams between the rectangles.
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" shape-rendering=\"crispEdges\">\n",
+        options.get_width(),
+        options.get_height()
+    )
+}
+
+/// Writes the paper, then layer 2 and layer 1 on top of it.
+fn write_frame_shapes(svg: &mut String, frame: &PPMFrame, options: &RenderOptions) -> Result<()> {
+    let pixels = frame.get_indexed_pixels_with(options)?;
+    let palette = frame.get_palette_with(options)?;
+
+    let width = options.get_width() as usize;
+    let height = options.get_height() as usize;
+
+    if !options.get_transparent_paper() {
+        writeln!(
+            svg,
+            "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            width,
+            height,
+            rgb_to_hex(&palette[0])
+        )?;
+    }
+
+    for layer in [2u8, 1] {
+        let rectangles = trace_rectangles(&pixels, width, height, layer);
+
+        if rectangles.is_empty() {
+            continue;
+        }
+
+        let mut path = String::new();
+
+        for (x, y, width, height) in rectangles {
+            write!(path, "M{} {}h{}v{}h-{}z", x, y, width, height, width)?;
+        }
+
+        writeln!(
+            svg,
+            "<path fill=\"{}\" d=\"{}\"/>",
+            rgb_to_hex(&palette[layer as usize]),
+            path
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Covers every pixel with the given palette index using as few rectangles as practical.
+/// Each row is split into runs, and a run that continues a run of the same span in the row above extends that rectangle downwards.
+fn trace_rectangles(
+    pixels: &[u8],
+    width: usize,
+    height: usize,
+    index: u8,
+) -> Vec<(usize, usize, usize, usize)> {
+    let mut rectangles = Vec::new();
+
+    //open rectangles by their span (start x, end x), with the row they started at.
+    let mut open: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+
+    for y in 0..=height {
+        let mut spans = Vec::new();
+
+        if y < height {
+            let row = &pixels[y * width..(y + 1) * width];
+
+            let mut x = 0;
+
+            while x < width {
+                if row[x] != index {
+                    x += 1;
+                    continue;
+                }
+
+                let start = x;
+
+                while x < width && row[x] == index {
+                    x += 1;
+                }
+
+                spans.push((start, x));
+            }
+        }
+
+        //close the rectangles that don't continue into this row.
+        open.retain(|(start, end), start_y| {
+            let continues = spans.contains(&(*start, *end));
+
+            if !continues {
+                rectangles.push((*start, *start_y, end - start, y - *start_y));
+            }
+
+            continues
+        });
+
+        for span in spans {
+            open.entry(span).or_insert(y);
+        }
+    }
+
+    rectangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::{get_test_file, get_test_frame};
+
+    fn get_pixels(rows: &[&str]) -> Vec<u8> {
+        rows.iter()
+            .flat_map(|row| row.bytes().map(|pixel| pixel - b'0'))
+            .collect()
+    }
+
+    /// Checks that the rectangles cover every pixel of `index` once, and nothing else.
+    fn assert_covers(pixels: &[u8], width: usize, height: usize, index: u8) {
+        let mut covered = vec![0; pixels.len()];
+
+        for (x, y, rectangle_width, rectangle_height) in
+            trace_rectangles(pixels, width, height, index)
+        {
+            for y in y..y + rectangle_height {
+                for x in x..x + rectangle_width {
+                    covered[y * width + x] += 1;
+                }
+            }
+        }
+
+        for (pixel, covered) in pixels.iter().zip(covered) {
+            assert_eq!(covered, (*pixel == index) as i32);
+        }
+    }
+
+    #[test]
+    fn rectangles_merge_rows_with_the_same_span() {
+        let l_shape = get_pixels(&["1000", "1000", "1000", "1110"]);
+
+        assert_eq!(
+            trace_rectangles(&l_shape, 4, 4, 1),
+            [(0, 0, 1, 3), (0, 3, 3, 1)]
+        );
+
+        let stacked = get_pixels(&["0110", "0110", "0110", "0000"]);
+
+        assert_eq!(trace_rectangles(&stacked, 4, 4, 1), [(1, 0, 2, 3)]);
+        assert!(trace_rectangles(&stacked, 4, 4, 2).is_empty());
+
+        let split = get_pixels(&["1101", "1111", "0101"]);
+
+        assert_covers(&split, 4, 3, 1);
+        assert_covers(&split, 4, 3, 0);
+    }
+
+    #[test]
+    fn rectangles_cover_exactly_the_frame_pixels() {
+        let pixels = get_test_frame(5).get_indexed_pixels().unwrap();
+
+        for index in 0..3 {
+            assert_covers(&pixels, 256, 192, index);
+        }
+    }
+
+    fn get_key_times(svg: &str) -> Vec<Vec<f64>> {
+        svg.split("keyTimes=\"")
+            .skip(1)
+            .map(|rest| {
+                rest[..rest.find('"').unwrap()]
+                    .split(';')
+                    .map(|time| time.parse().unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn animated_svg_key_times_start_at_0_and_increase() {
+        //4 fps, so the runs of 2, 1 and 3 frames are 500, 250 and 750 ms.
+        let file = get_test_file(&[0, 0, 1, 2, 2, 2], 4);
+
+        let mut svg = Vec::new();
+        write_animated_svg(&file, &mut svg, &RenderOptions::default()).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+
+        assert_eq!(svg.matches("dur=\"1500ms\"").count(), 3);
+        assert_eq!(
+            get_key_times(&svg),
+            [
+                vec![0.0, 0.333333],
+                vec![0.0, 0.333333, 0.5],
+                vec![0.0, 0.5]
+            ]
+        );
+
+        for key_times in get_key_times(&svg) {
+            assert!(
+                key_times
+                    .windows(2)
+                    .all(|pair| pair[0] < pair[1] && pair[1] <= 1.0)
+            );
+        }
+
+        //a single picture is visible for the whole animation.
+        let file = get_test_file(&[3, 3], 4);
+
+        let mut svg = Vec::new();
+        write_animated_svg(&file, &mut svg, &RenderOptions::default()).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+
+        assert_eq!(get_key_times(&svg), [vec![0.0]]);
+        assert!(svg.contains("values=\"visible\""));
+    }
+}
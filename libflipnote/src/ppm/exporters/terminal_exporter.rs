@@ -0,0 +1,614 @@
+//! Shows frames right in the terminal, with colored half-block characters or sixel graphics.
+
+use std::{
+    io::{Read, Write},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, bail, ensure};
+
+use crate::{
+    ppm::{
+        file::PPMFile,
+        frames::{
+            frame::PPMFrame,
+            render_options::{PaperMode, RenderOptions},
+        },
+    },
+    utils::image_utils::ResizeMode,
+};
+
+/// How frames are drawn in the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TerminalGraphics {
+    /// `▀` characters with 24-bit foreground and background colors, 2 pixels per cell. Works in nearly every modern terminal.
+    #[default]
+    HalfBlocks,
+    /// Full resolution sixel images, for terminals that support them like xterm, mlterm, foot and WezTerm.
+    Sixel,
+}
+
+/// Options for [`play_in_terminal`].
+#[derive(Debug, Clone, Default)]
+pub struct TerminalPlayerOptions {
+    /// Asks the terminal whether it can show sixels if `None`.
+    pub graphics: Option<TerminalGraphics>,
+    /// Half-blocks are scaled down to fit the terminal afterwards, sixels are drawn at this size. Transparent paper is drawn in its color.
+    pub render_options: RenderOptions,
+    pub start_frame: usize,
+}
+
+/// Writes a frame as rows of half-block characters, `width` cells wide and 3/4 of that in pixels tall, each row ending in a line break.
+/// The frame is scaled down smoothly to fit, so details that don't fit still show as shades.
+pub fn write_half_blocks(
+    frame: &PPMFrame,
+    mut writer: impl Write,
+    width: u32,
+    options: &RenderOptions,
+) -> Result<()> {
+    ensure!(width > 0, "The frame needs to be at least 1 cell wide");
+
+    let image = frame.get_image_with(options)?;
+
+    //rows are pairs of pixels, so the height is kept even.
+    let height = (width * 3 / 4).div_ceil(2).max(1) * 2;
+
+    let pixels = match (image.get_width(), image.get_height()) == (width, height) {
+        true => image,
+        false => image.resize_with_mode(
+            width,
+            height,
+            ResizeMode::Stretch,
+            &frame.get_palette_with(options)?[0],
+        )?,
+    }
+    .get_raw_pixels();
+
+    let mut output = Vec::new();
+
+    for y in (0..height as usize).step_by(2) {
+        let mut last_colors = None;
+
+        for x in 0..width as usize {
+            let top = &pixels[(y * width as usize + x) * 4..][..3];
+            let bottom = &pixels[((y + 1) * width as usize + x) * 4..][..3];
+
+            //only changes of color are written, flat areas are just characters.
+            if last_colors != Some((top, bottom)) {
+                write!(
+                    output,
+                    "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                    top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+                )?;
+
+                last_colors = Some((top, bottom));
+            }
+
+            output.extend_from_slice("▀".as_bytes());
+        }
+
+        output.extend_from_slice(b"\x1b[0m\r\n");
+    }
+
+    writer.write_all(&output)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes a frame as a sixel image of [`RenderOptions::get_width`] by [`RenderOptions::get_height`] pixels. Transparent paper is left transparent.
+/// Sixels use the frame's 3 colors, so the subpixels of the LCD emulation are left out.
+pub fn write_sixel(
+    frame: &PPMFrame,
+    mut writer: impl Write,
+    options: &RenderOptions,
+) -> Result<()> {
+    let indices = frame.get_indexed_pixels_with(options)?;
+    let colors = frame.get_palette_with(options)?;
+    let transparent_paper = options.get_transparent_paper();
+
+    let (width, height) = (options.get_width() as usize, options.get_height() as usize);
+
+    let mut output = Vec::new();
+
+    //the second parameter makes pixels that aren't drawn keep the background.
+    write!(
+        output,
+        "\x1bP0;{};0q\"1;1;{};{}",
+        transparent_paper as u8, width, height
+    )?;
+
+    for (i, color) in colors.iter().enumerate() {
+        //sixel colors are percentages.
+        let percent = |value: u8| (value as u32 * 100 + 127) / 255;
+
+        write!(
+            output,
+            "#{};2;{};{};{}",
+            i,
+            percent(color.r),
+            percent(color.g),
+            percent(color.b)
+        )?;
+    }
+
+    let first_color = match transparent_paper {
+        true => 1,
+        false => 0,
+    };
+
+    //every band is 6 rows, one character per column holding a bit for each row.
+    for band_y in (0..height).step_by(6) {
+        for color in first_color..colors.len() as u8 {
+            let sixels = (0..width)
+                .map(|x| {
+                    (0..6)
+                        .filter(|row| {
+                            let y = band_y + row;
+                            y < height && indices[y * width + x] == color
+                        })
+                        .fold(0u8, |sixel, row| sixel | 1 << row)
+                })
+                .collect::<Vec<u8>>();
+
+            if sixels.iter().all(|sixel| *sixel == 0) {
+                continue;
+            }
+
+            write!(output, "#{}", color)?;
+            write_sixel_runs(&mut output, &sixels)?;
+
+            //goes back to the start of the band for the next color.
+            output.push(b'$');
+        }
+
+        //the last band doesn't move on, so the cursor ends up right below the image.
+        if band_y + 6 < height {
+            output.push(b'-');
+        }
+    }
+
+    output.extend_from_slice(b"\x1b\\");
+
+    writer.write_all(&output)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes sixels with repeats of 4 or more run length encoded.
+fn write_sixel_runs(output: &mut Vec<u8>, sixels: &[u8]) -> Result<()> {
+    let mut i = 0;
+
+    while i < sixels.len() {
+        let run = sixels[i..]
+            .iter()
+            .take_while(|sixel| **sixel == sixels[i])
+            .count();
+        let character = sixels[i] + 0x3F;
+
+        match run {
+            1..=3 => output.extend(std::iter::repeat_n(character, run)),
+            _ => write!(output, "!{}{}", run, character as char)?,
+        }
+
+        i += run;
+    }
+
+    Ok(())
+}
+
+/// Asks the terminal on stdin and stdout whether it supports sixels, falling back to half-blocks if it doesn't answer.
+pub fn detect_graphics() -> Result<TerminalGraphics> {
+    let mut terminal = RawTerminal::enter(false)?;
+
+    terminal.query_graphics()
+}
+
+/// Plays a flipnote in the terminal at its framerate, looping if its loop flag is set, until q, Esc or Ctrl+C is pressed.
+/// Space pauses, the left and right arrow keys (or h and l) step through the frames one at a time.
+pub fn play_in_terminal(file: &PPMFile, options: &TerminalPlayerOptions) -> Result<()> {
+    options.render_options.validate()?;
+
+    let frames = file.animation_data.get_frames()?;
+
+    ensure!(!frames.is_empty(), "The flipnote has no frames");
+
+    let framerate = file.audio.audio_header.get_framerate()?;
+    let looping = file.animation_data.get_animation_flags().get_loop();
+    let frame_duration = Duration::from_secs_f32(1.0 / framerate);
+
+    //transparent sixels would leave the previous frame showing through.
+    let render_options = match options.render_options.paper {
+        PaperMode::Transparent => RenderOptions {
+            paper: PaperMode::Frame,
+            ..options.render_options.clone()
+        },
+        _ => options.render_options.clone(),
+    };
+
+    let mut terminal = RawTerminal::enter(true)?;
+
+    let graphics = match options.graphics {
+        Some(graphics) => graphics,
+        None => terminal.query_graphics()?,
+    };
+
+    let mut index = options.start_frame.min(frames.len() - 1);
+    let mut playing = true;
+    let mut next_frame = Instant::now() + frame_duration;
+    let mut last_size = None;
+    let mut redraw = true;
+
+    loop {
+        if redraw {
+            let size = get_terminal_size();
+
+            //a smaller terminal would leave parts of the last, larger frame behind.
+            if last_size != Some(size) {
+                terminal.write(b"\x1b[2J")?;
+                last_size = Some(size);
+            }
+
+            let mut output = Vec::new();
+
+            write!(
+                output,
+                "\x1b[H\x1b[0mFrame {}/{}  {} fps  {}{}\x1b[K\r\n",
+                index + 1,
+                frames.len(),
+                framerate,
+                match playing {
+                    true => "playing",
+                    false => "paused",
+                },
+                match looping {
+                    true => "  loop",
+                    false => "",
+                },
+            )?;
+
+            match graphics {
+                TerminalGraphics::HalfBlocks => {
+                    let (columns, rows) = size;
+                    //the status line takes a row, and the line break after the last row of pixels another so it doesn't scroll. Every other row holds 2 pixels.
+                    let width = columns
+                        .min(256)
+                        .min(rows.saturating_sub(2) * 2 * 4 / 3)
+                        .max(4);
+
+                    write_half_blocks(&frames[index], &mut output, width, &render_options)?;
+                }
+                TerminalGraphics::Sixel => {
+                    write_sixel(&frames[index], &mut output, &render_options)?
+                }
+            }
+
+            terminal.write(&output)?;
+            redraw = false;
+        }
+
+        let timeout = match playing {
+            true => Some(next_frame.saturating_duration_since(Instant::now())),
+            false => None,
+        };
+
+        let keys = terminal.read_keys(timeout)?;
+
+        //poll also returns early, for keys that aren't used or when the terminal is resized.
+        if keys.is_empty() {
+            if playing && Instant::now() >= next_frame {
+                match (index + 1 < frames.len(), looping) {
+                    (true, _) => index += 1,
+                    (false, true) => index = 0,
+                    (false, false) => playing = false,
+                }
+
+                next_frame += frame_duration;
+
+                //starts over after drawing fell behind instead of rushing through the missed frames.
+                if next_frame < Instant::now() {
+                    next_frame = Instant::now() + frame_duration;
+                }
+
+                redraw = true;
+            }
+
+            redraw |= last_size != Some(get_terminal_size());
+        }
+
+        for key in keys {
+            match key {
+                Key::Quit => return Ok(()),
+                Key::TogglePause => {
+                    playing = !playing;
+                    next_frame = Instant::now() + frame_duration;
+
+                    //playing again after the end of a flipnote that doesn't loop starts it over.
+                    if playing && !looping && index + 1 == frames.len() {
+                        index = 0;
+                    }
+                }
+                Key::Next => {
+                    playing = false;
+                    index = (index + 1) % frames.len();
+                }
+                Key::Previous => {
+                    playing = false;
+                    index = (index + frames.len() - 1) % frames.len();
+                }
+            }
+
+            redraw = true;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Quit,
+    TogglePause,
+    Next,
+    Previous,
+}
+
+/// Turns the bytes read from the terminal into keys, leaving out the ones that aren't used.
+fn parse_keys(input: &[u8]) -> Vec<Key> {
+    //a lone escape is the escape key, otherwise it starts the sequence of an arrow key.
+    if input == b"\x1b" {
+        return vec![Key::Quit];
+    }
+
+    let mut keys = Vec::new();
+    let mut rest = input;
+
+    while let Some(first) = rest.first() {
+        let (key, length) = match rest {
+            [0x1b, b'[' | b'O', b'C', ..] => (Some(Key::Next), 3),
+            [0x1b, b'[' | b'O', b'D', ..] => (Some(Key::Previous), 3),
+            _ => match first {
+                b'q' | b'Q' | 0x03 => (Some(Key::Quit), 1),
+                b' ' => (Some(Key::TogglePause), 1),
+                b'l' | b'.' => (Some(Key::Next), 1),
+                b'h' | b',' => (Some(Key::Previous), 1),
+                _ => (None, 1),
+            },
+        };
+
+        keys.extend(key);
+        rest = &rest[length..];
+    }
+
+    keys
+}
+
+/// Puts the terminal into raw mode for reading single key presses, and restores it when dropped.
+struct RawTerminal {
+    original: libc::termios,
+    alternate_screen: bool,
+}
+
+impl RawTerminal {
+    /// Switches to raw mode, and to a separate screen with a hidden cursor if `alternate_screen` is set.
+    fn enter(alternate_screen: bool) -> Result<Self> {
+        let is_terminal = unsafe {
+            libc::isatty(libc::STDIN_FILENO) == 1 && libc::isatty(libc::STDOUT_FILENO) == 1
+        };
+
+        ensure!(is_terminal, "stdin and stdout need to be a terminal");
+
+        let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+
+        let result = unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) };
+        ensure!(result == 0, std::io::Error::last_os_error());
+
+        let mut raw = original;
+
+        //signals are turned off too, Ctrl+C is read as a key so the terminal is always restored.
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 0;
+
+        let result = unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) };
+        ensure!(result == 0, std::io::Error::last_os_error());
+
+        let mut terminal = Self {
+            original,
+            alternate_screen,
+        };
+
+        if alternate_screen {
+            terminal.write(b"\x1b[?1049h\x1b[?25l")?;
+        }
+
+        Ok(terminal)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        let mut stdout = std::io::stdout().lock();
+
+        stdout.write_all(data)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for input, or forever if it is `None`, and returns whatever was read. Returns nothing once the time is up.
+    fn read_input(&mut self, timeout: Option<Duration>) -> Result<Vec<u8>> {
+        let mut poll_fd = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        let timeout = match timeout {
+            Some(timeout) => timeout.as_millis().min(i32::MAX as u128) as i32,
+            None => -1,
+        };
+
+        let result = unsafe { libc::poll(&mut poll_fd, 1, timeout) };
+
+        match result {
+            0 => return Ok(vec![]),
+            //a signal like a resize of the terminal, the caller just waits again.
+            -1 if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => {
+                return Ok(vec![]);
+            }
+            -1 => bail!(std::io::Error::last_os_error()),
+            _ => {}
+        }
+
+        let mut buffer = [0u8; 64];
+        let read = std::io::stdin().lock().read(&mut buffer)?;
+
+        //readable without any input means the terminal has gone away.
+        ensure!(read > 0, "The terminal was closed");
+
+        Ok(buffer[..read].to_vec())
+    }
+
+    /// Reads the keys pressed since the last call, waiting up to `timeout` for one. Keys that aren't used are left out.
+    fn read_keys(&mut self, timeout: Option<Duration>) -> Result<Vec<Key>> {
+        Ok(parse_keys(&self.read_input(timeout)?))
+    }
+
+    /// Sends a primary device attributes request, which terminals answer with a list of features where 4 means sixel.
+    fn query_graphics(&mut self) -> Result<TerminalGraphics> {
+        self.write(b"\x1b[c")?;
+
+        let deadline = Instant::now() + Duration::from_millis(500);
+        let mut response = Vec::new();
+
+        while !response.ends_with(b"c") {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            if remaining.is_zero() {
+                return Ok(TerminalGraphics::HalfBlocks);
+            }
+
+            response.extend(self.read_input(Some(remaining))?);
+        }
+
+        let response = String::from_utf8_lossy(&response);
+
+        let supports_sixel = response
+            .trim_start_matches(|c: char| !c.is_ascii_digit())
+            .trim_end_matches('c')
+            .split(';')
+            .any(|feature| feature == "4");
+
+        match supports_sixel {
+            true => Ok(TerminalGraphics::Sixel),
+            false => Ok(TerminalGraphics::HalfBlocks),
+        }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        if self.alternate_screen {
+            let _ = self.write(b"\x1b[0m\x1b[?25h\x1b[?1049l");
+        }
+
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Returns the columns and rows of the terminal on stdout, or 80x24 if it can't be asked.
+pub fn get_terminal_size() -> (u32, u32) {
+    let mut size = unsafe { std::mem::zeroed::<libc::winsize>() };
+
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+
+    match result == 0 && size.ws_col > 0 && size.ws_row > 0 {
+        true => (size.ws_col as u32, size.ws_row as u32),
+        false => (80, 24),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ppm::frames::frame_header::{PPMLayerColor, PPMPaperColor},
+        utils::image_utils::RgbWrapper,
+    };
+
+    #[test]
+    fn parse_keys_reads_letters_and_arrow_keys() {
+        assert_eq!(parse_keys(b"\x1b"), [Key::Quit]);
+        assert_eq!(
+            parse_keys(b" \x1b[C\x1bOD,x.\x03"),
+            [
+                Key::TogglePause,
+                Key::Next,
+                Key::Previous,
+                Key::Previous,
+                Key::Next,
+                Key::Quit
+            ]
+        );
+        //arrow keys that aren't used, like up and down, are skipped whole.
+        assert_eq!(parse_keys(b"\x1b[Aq"), [Key::Quit]);
+        assert!(parse_keys(b"").is_empty());
+    }
+
+    #[test]
+    fn write_sixel_runs_encodes_runs_of_4_or_more() {
+        let mut output = Vec::new();
+
+        write_sixel_runs(&mut output, &[0, 0, 0, 0, 0, 1, 1, 1, 63]).unwrap();
+
+        assert_eq!(output, b"!5?@@@~");
+    }
+
+    #[test]
+    fn write_half_blocks_writes_2_pixels_per_cell() {
+        let mut frame = PPMFrame::default();
+        frame.get_header_mut().set_paper_color(PPMPaperColor::White);
+        frame
+            .get_header_mut()
+            .set_layer_color(1, PPMLayerColor::Red)
+            .unwrap();
+        frame.get_layer_mut(1).unwrap().set(0, 0, true).unwrap();
+
+        let [paper, red, _] = frame.get_palette().unwrap();
+        let color = |top: RgbWrapper, bottom: RgbWrapper| {
+            format!(
+                "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                top.r, top.g, top.b, bottom.r, bottom.g, bottom.b
+            )
+        };
+
+        let mut output = Vec::new();
+        write_half_blocks(&frame, &mut output, 256, &RenderOptions::default()).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        let lines = output.split_terminator("\r\n").collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 96);
+        assert_eq!(
+            lines[0],
+            format!(
+                "{}▀{}{}\x1b[0m",
+                color(red, paper),
+                color(paper, paper),
+                "▀".repeat(255)
+            )
+        );
+        assert_eq!(
+            lines[1],
+            format!("{}{}\x1b[0m", color(paper, paper), "▀".repeat(256))
+        );
+
+        //scaled down, 3/4 of the width in pixels is 2 rows of cells.
+        let mut output = Vec::new();
+        write_half_blocks(&frame, &mut output, 4, &RenderOptions::default()).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(output.matches("\r\n").count(), 2);
+        assert_eq!(output.matches('▀').count(), 8);
+    }
+}
@@ -0,0 +1,478 @@
+use std::{
+    ffi::CString,
+    fs::{DirBuilder, File, OpenOptions},
+    io::{ErrorKind, Read, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{DirBuilderExt, OpenOptionsExt},
+        io::AsRawFd,
+    },
+    path::{Path, PathBuf},
+    process::{ChildStdin, Command, Stdio},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, bail, ensure};
+
+use crate::ppm::{
+    file::PPMFile,
+    frames::{frame::PPMFrame, render_options::RenderOptions},
+};
+
+use super::raw_exporter;
+
+/// Options for encoding videos through ffmpeg, built like `VideoExportOptions::new().repetitions(3).include_audio(false)`.
+#[derive(Debug, Clone)]
+pub struct VideoExportOptions {
+    ffmpeg_path: PathBuf,
+    video_codec_args: Vec<String>,
+    audio_codec_args: Vec<String>,
+    output_args: Vec<String>,
+    render_options: RenderOptions,
+    repetitions: u32,
+    audio_sample_rate: i32,
+    include_audio: bool,
+}
+
+impl Default for VideoExportOptions {
+    fn default() -> Self {
+        Self {
+            ffmpeg_path: PathBuf::from("ffmpeg"),
+            video_codec_args: to_args(&["-c:v", "libx264", "-pix_fmt", "yuv420p"]),
+            audio_codec_args: vec![],
+            output_args: vec![],
+            render_options: RenderOptions::default(),
+            repetitions: 1,
+            audio_sample_rate: 44100,
+            include_audio: true,
+        }
+    }
+}
+
+impl VideoExportOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The ffmpeg executable, looked up in `PATH` by default.
+    pub fn ffmpeg_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ffmpeg_path = path.into();
+        self
+    }
+
+    /// Arguments choosing the video codec, `-c:v libx264 -pix_fmt yuv420p` by default.
+    pub fn video_codec_args(mut self, args: &[&str]) -> Self {
+        self.video_codec_args = to_args(args);
+        self
+    }
+
+    /// Arguments choosing the audio codec, ffmpeg picks one for the container by default.
+    pub fn audio_codec_args(mut self, args: &[&str]) -> Self {
+        self.audio_codec_args = to_args(args);
+        self
+    }
+
+    /// Arguments placed right before the output path, e.g. `-f mp4` or `-movflags +faststart`.
+    pub fn output_args(mut self, args: &[&str]) -> Self {
+        self.output_args = to_args(args);
+        self
+    }
+
+    /// How the frames are rendered before they are encoded, e.g. at 4x with an upscaler.
+    /// Most codecs have no alpha, so transparent paper usually needs something like `-c:v png` or `-c:v prores_ks -pix_fmt yuva444p10le`.
+    pub fn render_options(mut self, options: RenderOptions) -> Self {
+        self.render_options = options;
+        self
+    }
+
+    /// How many times the animation plays in the video, together with its audio.
+    pub fn repetitions(mut self, repetitions: u32) -> Self {
+        self.repetitions = repetitions;
+        self
+    }
+
+    pub fn audio_sample_rate(mut self, sample_rate: i32) -> Self {
+        self.audio_sample_rate = sample_rate;
+        self
+    }
+
+    /// Leaves the audio out if false. Flipnotes without audio always make silent videos.
+    pub fn include_audio(mut self, value: bool) -> Self {
+        self.include_audio = value;
+        self
+    }
+}
+
+/// Encodes the flipnote into a video with ffmpeg at its exact framerate, e.g. `1/2` for 0.5 fps.
+/// Frames are piped to ffmpeg's stdin and the audio through a fifo in a private temporary directory, each written from its own thread.
+/// If ffmpeg fails, the error contains what it wrote to stderr.
+pub fn export_video(
+    file: &PPMFile,
+    path: impl Into<PathBuf>,
+    options: &VideoExportOptions,
+) -> Result<()> {
+    let path: PathBuf = path.into();
+
+    options.render_options.validate()?;
+    ensure!(options.repetitions > 0, "Repetitions must be at least 1");
+    ensure!(
+        options.audio_sample_rate > 0,
+        "Audio sample rate must be positive"
+    );
+
+    let frames = file.animation_data.get_frames()?;
+    let (numerator, denominator) = file.audio.audio_header.get_framerate_ratio()?;
+
+    let audio = match file.audio.mixed_tracks.is_some() && options.include_audio {
+        true => Some(
+            raw_exporter::get_timeline_audio(file, options.audio_sample_rate, options.repetitions)?
+                .iter()
+                .flat_map(|sample| sample.to_le_bytes())
+                .collect::<Vec<u8>>(),
+        ),
+        false => None,
+    };
+
+    let fifo_directory = match audio {
+        Some(_) => Some(FifoDirectory::new()?),
+        None => None,
+    };
+
+    let mut command = Command::new(&options.ffmpeg_path);
+
+    command
+        .args(["-y", "-hide_banner", "-loglevel", "error"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+        .args([
+            "-video_size",
+            &format!(
+                "{}x{}",
+                options.render_options.get_width(),
+                options.render_options.get_height()
+            ),
+        ])
+        .args(["-framerate", &format!("{}/{}", numerator, denominator)])
+        .args(["-i", "pipe:0"]);
+
+    let audio_fifo = match &fifo_directory {
+        Some(fifo_directory) => {
+            let audio_fifo = fifo_directory.create_fifo("audio")?;
+
+            command
+                .args(["-f", "s16le", "-ac", "1"])
+                .args(["-sample_rate", &options.audio_sample_rate.to_string()])
+                .arg("-i")
+                .arg(&audio_fifo);
+
+            Some(audio_fifo)
+        }
+        None => None,
+    };
+
+    command.args(&options.video_codec_args);
+
+    if audio_fifo.is_some() {
+        command.args(&options.audio_codec_args);
+    }
+
+    command.args(&options.output_args).arg(&path);
+
+    let mut ffmpeg = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to start {}", options.ffmpeg_path.display()))?;
+
+    let stdin = ffmpeg.stdin.take().context("ffmpeg stdin is not piped")?;
+    let mut stderr = ffmpeg.stderr.take().context("ffmpeg stderr is not piped")?;
+
+    //stderr is drained separately so ffmpeg can't block on a full pipe.
+    let stderr_reader = std::thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stderr.read_to_string(&mut output);
+        output
+    });
+
+    let video_writer = spawn_video_writer(
+        stdin,
+        frames,
+        options.render_options.clone(),
+        options.repetitions,
+    );
+
+    let ffmpeg_exited = Arc::new(AtomicBool::new(false));
+
+    let audio_writer = match (audio_fifo, audio) {
+        (Some(audio_fifo), Some(audio)) => {
+            Some(spawn_audio_writer(audio_fifo, audio, ffmpeg_exited.clone()))
+        }
+        _ => None,
+    };
+
+    let status = ffmpeg.wait()?;
+    ffmpeg_exited.store(true, Ordering::SeqCst);
+
+    let video_result = join_writer(video_writer);
+    let audio_result = audio_writer.map(join_writer).unwrap_or(Ok(()));
+    let stderr = stderr_reader.join().unwrap_or_default();
+
+    //the writers fail with a broken pipe when ffmpeg stops early, ffmpeg's own error is more useful.
+    if !status.success() {
+        bail!("ffmpeg failed: {:?}\n{}", status, stderr);
+    }
+
+    video_result.context("Failed to write frames to ffmpeg")?;
+    audio_result.context("Failed to write audio to ffmpeg")?;
+
+    Ok(())
+}
+
+fn spawn_video_writer(
+    mut stdin: ChildStdin,
+    frames: Vec<PPMFrame>,
+    render_options: RenderOptions,
+    repetitions: u32,
+) -> JoinHandle<Result<()>> {
+    std::thread::spawn(move || {
+        for _ in 0..repetitions {
+            for frame in frames.iter() {
+                stdin.write_all(&frame.get_image_with(&render_options)?.get_raw_pixels())?;
+            }
+        }
+
+        //dropping stdin tells ffmpeg the video has ended.
+        Ok(())
+    })
+}
+
+/// Writes the audio into the fifo once ffmpeg opens it.
+/// Opening a fifo blocks until the other side opens it too, so the writer polls instead, and gives up if ffmpeg exits without ever reading it.
+fn spawn_audio_writer(
+    fifo: PathBuf,
+    audio: Vec<u8>,
+    ffmpeg_exited: Arc<AtomicBool>,
+) -> JoinHandle<Result<()>> {
+    std::thread::spawn(move || {
+        let mut fifo = loop {
+            match OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&fifo)
+            {
+                Ok(fifo) => break fifo,
+                //no reader yet.
+                Err(e) if e.raw_os_error() == Some(libc::ENXIO) => {
+                    if ffmpeg_exited.load(Ordering::SeqCst) {
+                        return Ok(());
+                    }
+
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        set_blocking(&fifo)?;
+
+        match fifo.write_all(&audio) {
+            Err(e) if e.kind() == ErrorKind::BrokenPipe && ffmpeg_exited.load(Ordering::SeqCst) => {
+                Ok(())
+            }
+            result => Ok(result?),
+        }
+    })
+}
+
+fn join_writer(writer: JoinHandle<Result<()>>) -> Result<()> {
+    writer
+        .join()
+        .map_err(|_| anyhow::anyhow!("The writer thread panicked"))?
+}
+
+fn set_blocking(file: &File) -> Result<()> {
+    let fd = file.as_raw_fd();
+
+    let result = unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_NONBLOCK)
+    };
+
+    ensure!(result != -1, std::io::Error::last_os_error());
+
+    Ok(())
+}
+
+fn to_args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// A temporary directory only we can access, holding the fifos for ffmpeg. It is removed when dropped.
+struct FifoDirectory {
+    path: PathBuf,
+}
+
+impl FifoDirectory {
+    fn new() -> Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.subsec_nanos();
+
+        let path = std::env::temp_dir().join(format!(
+            "paracule-{}-{}-{}",
+            std::process::id(),
+            nanos,
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        //fails if the directory exists, so nobody else can have put a file there.
+        DirBuilder::new()
+            .mode(0o700)
+            .create(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+
+        Ok(Self { path })
+    }
+
+    fn create_fifo(&self, name: &str) -> Result<PathBuf> {
+        let path = self.path.join(name);
+
+        make_fifo(&path)?;
+
+        Ok(path)
+    }
+}
+
+impl Drop for FifoDirectory {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+fn make_fifo(path: &Path) -> Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+
+    let result = unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) };
+
+    ensure!(
+        result == 0,
+        "Failed to create fifo {}: {}",
+        path.display(),
+        std::io::Error::last_os_error()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ppm::{audio::wav_container::WavContainer, file_builder::PPMFileBuilder},
+        utils::test_utils::{get_temp_dir, write_script},
+    };
+
+    /// 2 blank frames at 0.5 fps, with 4 seconds of BGM.
+    fn get_test_file() -> PPMFile {
+        PPMFileBuilder::new()
+            .frames(vec![PPMFrame::default(); 2])
+            .speed(1)
+            .background_track(WavContainer::from_samples(
+                vec![1000; 8192 * 4],
+                1,
+                8192,
+                16,
+            ))
+            .seed(1)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn export_video_passes_args_and_drains_both_streams() {
+        let dir = get_temp_dir("export-video");
+
+        //logs every argument, then reads the audio fifo (the input that isn't stdin) and stdin at the same time, like ffmpeg does.
+        let ffmpeg = write_script(
+            &dir,
+            "ffmpeg",
+            &format!(
+                r#"cd "{}"
+previous=""
+audio=""
+for arg in "$@"; do
+  echo "$arg" >> args
+  if [ "$previous" = "-i" ] && [ "$arg" != "pipe:0" ]; then audio="$arg"; fi
+  previous="$arg"
+done
+cat "$audio" > audio.raw &
+cat > video.raw
+wait
+"#,
+                dir.display()
+            ),
+        );
+
+        let options = VideoExportOptions::new()
+            .ffmpeg_path(ffmpeg)
+            .repetitions(2)
+            .audio_sample_rate(8000)
+            .output_args(&["-f", "mp4"]);
+
+        export_video(&get_test_file(), dir.join("out.mp4"), &options).unwrap();
+
+        let args = std::fs::read_to_string(dir.join("args")).unwrap();
+        let args = args.lines().collect::<Vec<_>>();
+
+        let get_value = |name: &str| args[args.iter().position(|arg| *arg == name).unwrap() + 1];
+
+        assert_eq!(get_value("-framerate"), "1/2");
+        assert_eq!(get_value("-video_size"), "256x192");
+        assert_eq!(get_value("-pix_fmt"), "rgba");
+        assert_eq!(get_value("-sample_rate"), "8000");
+        assert_eq!(get_value("-c:v"), "libx264");
+        assert_eq!(
+            args[args.len() - 3..],
+            ["-f", "mp4", dir.join("out.mp4").to_str().unwrap()]
+        );
+
+        //2 frames played twice, and 2 times 4 seconds of 16 bit samples.
+        assert_eq!(
+            std::fs::metadata(dir.join("video.raw")).unwrap().len(),
+            2 * 2 * 256 * 192 * 4
+        );
+        assert_eq!(
+            std::fs::metadata(dir.join("audio.raw")).unwrap().len(),
+            2 * 4 * 8000 * 2
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn export_video_returns_ffmpeg_stderr() {
+        let dir = get_temp_dir("export-video-error");
+
+        let ffmpeg = write_script(
+            &dir,
+            "ffmpeg",
+            "echo \"Unknown encoder 'nope'\" >&2\nexit 3\n",
+        );
+
+        let error = export_video(
+            &get_test_file(),
+            dir.join("out.mp4"),
+            &VideoExportOptions::new().ffmpeg_path(ffmpeg),
+        )
+        .unwrap_err();
+
+        assert!(error.to_string().contains("Unknown encoder 'nope'"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
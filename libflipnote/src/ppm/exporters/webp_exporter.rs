@@ -0,0 +1,215 @@
+use std::io::Write;
+
+use anyhow::{Result, ensure};
+use image_webp::{ColorType, WebPEncoder};
+
+use crate::ppm::file::PPMFile;
+
+use super::{AnimationExportOptions, get_frame_runs, get_frame_time};
+
+/// Writes the flipnote as a lossless animated WebP.
+/// The frames are encoded as still VP8L images, then wrapped in the animation chunks of the extended WebP format.
+/// Runs of identical frames are merged into one longer frame. Delays are rounded to milliseconds against the running time.
+pub fn write_webp(
+    file: &PPMFile,
+    mut writer: impl Write,
+    options: &AnimationExportOptions,
+) -> Result<()> {
+    let render_options = &options.render_options;
+    render_options.validate()?;
+
+    let frames = file.animation_data.get_frames()?;
+    let framerate = file.audio.audio_header.get_framerate()?;
+
+    let width = render_options.get_width();
+    let height = render_options.get_height();
+    let transparent_paper = render_options.get_transparent_paper();
+
+    let mut chunks = vec![];
+
+    //canvas flags: animation, plus alpha if the paper is transparent.
+    let mut vp8x = vec![0u8; 10];
+    vp8x[0] = match transparent_paper {
+        true => 0x12,
+        false => 0x02,
+    };
+    vp8x[4..7].copy_from_slice(&(width - 1).to_le_bytes()[..3]);
+    vp8x[7..10].copy_from_slice(&(height - 1).to_le_bytes()[..3]);
+    write_chunk(&mut chunks, b"VP8X", &vp8x)?;
+
+    //background color, then the loop count where 0 plays forever.
+    let mut anim = vec![0u8; 4];
+    let loop_count: u16 = match file.animation_data.get_animation_flags().get_loop() {
+        true => 0,
+        false => 1,
+    };
+    anim.extend(loop_count.to_le_bytes());
+    write_chunk(&mut chunks, b"ANIM", &anim)?;
+
+    for run in get_frame_runs(&frames, render_options)? {
+        let duration = get_frame_time(run.frame_index + run.length, framerate, 1000.0)
+            - get_frame_time(run.frame_index, framerate, 1000.0);
+
+        ensure!(
+            duration < 1 << 24,
+            "Frame {} is too long for a WebP frame",
+            run.frame_index
+        );
+
+        let pixels = frames[run.frame_index]
+            .get_image_with(render_options)?
+            .get_raw_pixels();
+
+        let (pixels, color_type) = match transparent_paper {
+            true => (pixels, ColorType::Rgba8),
+            false => (
+                pixels
+                    .chunks_exact(4)
+                    .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                    .collect(),
+                ColorType::Rgb8,
+            ),
+        };
+
+        let mut still = vec![];
+        WebPEncoder::new(&mut still).encode(&pixels, width, height, color_type)?;
+
+        //a simple WebP is RIFF, size, WEBP, then the VP8L chunk header and its data.
+        let vp8l_size = u32::from_le_bytes([still[16], still[17], still[18], still[19]]) as usize;
+        let vp8l = &still[20..20 + vp8l_size];
+
+        //frame position / 2 (always 0), size - 1, duration, then flags: don't blend, don't dispose.
+        let mut anmf = vec![0u8; 6];
+        anmf.extend(&(width - 1).to_le_bytes()[..3]);
+        anmf.extend(&(height - 1).to_le_bytes()[..3]);
+        anmf.extend(&duration.to_le_bytes()[..3]);
+        anmf.push(0x02);
+        write_chunk(&mut anmf, b"VP8L", vp8l)?;
+
+        write_chunk(&mut chunks, b"ANMF", &anmf)?;
+    }
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(4 + chunks.len() as u32).to_le_bytes())?;
+    writer.write_all(b"WEBP")?;
+    writer.write_all(&chunks)?;
+
+    Ok(())
+}
+
+/// Writes a RIFF chunk, padded to an even size.
+fn write_chunk(writer: &mut Vec<u8>, name: &[u8; 4], data: &[u8]) -> Result<()> {
+    writer.write_all(name)?;
+    writer.write_all(&(data.len() as u32).to_le_bytes())?;
+    writer.write_all(data)?;
+
+    if data.len() % 2 == 1 {
+        writer.push(0);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image_webp::{LoopCount, WebPDecoder};
+
+    use super::*;
+    use crate::{
+        ppm::frames::render_options::{PaperMode, RenderOptions},
+        utils::test_utils::get_test_file,
+    };
+
+    /// Splits RIFF chunk data into the name and data of every chunk, skipping the padding.
+    fn get_chunks(mut data: &[u8]) -> Vec<(&[u8], &[u8])> {
+        let mut chunks = vec![];
+
+        while !data.is_empty() {
+            let size = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+
+            chunks.push((&data[..4], &data[8..8 + size]));
+            data = &data[(8 + size).next_multiple_of(2)..];
+        }
+
+        chunks
+    }
+
+    fn write(paper: PaperMode) -> Vec<u8> {
+        //30 fps, the first picture shows for 2 frames.
+        let file = get_test_file(&[0, 0, 1], 8);
+
+        let options = AnimationExportOptions {
+            render_options: RenderOptions {
+                paper,
+                ..Default::default()
+            },
+        };
+
+        let mut webp = vec![];
+        write_webp(&file, &mut webp, &options).unwrap();
+
+        webp
+    }
+
+    #[test]
+    fn webp_has_an_animation_frame_per_run() {
+        let webp = write(PaperMode::Frame);
+
+        assert_eq!(&webp[..4], b"RIFF");
+        assert_eq!(
+            u32::from_le_bytes(webp[4..8].try_into().unwrap()) as usize,
+            webp.len() - 8
+        );
+        assert_eq!(&webp[8..12], b"WEBP");
+
+        let chunks = get_chunks(&webp[12..]);
+        let names = chunks.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+
+        assert_eq!(names, [b"VP8X", b"ANIM", b"ANMF", b"ANMF"]);
+
+        //animation flag without alpha, and a 256x192 canvas stored as size - 1.
+        assert_eq!(chunks[0].1, [0x02, 0, 0, 0, 255, 0, 0, 191, 0, 0]);
+        //no background color, played once.
+        assert_eq!(chunks[1].1, [0, 0, 0, 0, 1, 0]);
+
+        //67 ms and 33 ms, rounded against the running time of 100 ms.
+        for ((_, anmf), duration) in chunks[2..].iter().zip([67u32, 33]) {
+            assert_eq!(anmf[12..15], duration.to_le_bytes()[..3]);
+            assert_eq!(anmf[15], 0x02);
+            assert_eq!(get_chunks(&anmf[16..])[0].0, b"VP8L");
+        }
+
+        let decoder = WebPDecoder::new(Cursor::new(webp)).unwrap();
+
+        assert!(decoder.is_animated());
+        assert!(!decoder.has_alpha());
+        assert_eq!(decoder.num_frames(), 2);
+        assert_eq!(
+            decoder.loop_count(),
+            LoopCount::Times(1.try_into().unwrap())
+        );
+    }
+
+    #[test]
+    fn webp_with_transparent_paper_has_alpha() {
+        let webp = write(PaperMode::Transparent);
+
+        assert_eq!(get_chunks(&webp[12..])[0].1[0], 0x12);
+        assert!(WebPDecoder::new(Cursor::new(webp)).unwrap().has_alpha());
+    }
+
+    #[test]
+    fn odd_chunks_are_padded() {
+        let mut data = vec![];
+
+        write_chunk(&mut data, b"ODD ", &[1, 2, 3]).unwrap();
+        write_chunk(&mut data, b"EVEN", &[4, 5]).unwrap();
+
+        assert_eq!(
+            data,
+            b"ODD \x03\x00\x00\x00\x01\x02\x03\x00EVEN\x02\x00\x00\x00\x04\x05"
+        );
+    }
+}
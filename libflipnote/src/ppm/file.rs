@@ -1,21 +1,45 @@
 use std::{
     fs::File,
-    io::Write,
+    io::{BufWriter, Write},
     path::PathBuf,
-    process::{Command, Stdio},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::{Result, bail, ensure};
+use anyhow::{Result, ensure};
 use binrw::{BinRead, BinWrite, binrw};
+use rand::RngCore;
 use rsa::{Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey, pkcs8::DecodePublicKey, rand_core};
 use sha1_checked::Sha1;
 
-use crate::utils::crypto::hash_data;
+use crate::utils::{crypto::hash_data, image_utils::ImageWrapper};
 
 use super::{
-    audio::audio_data::PPMAudio,
-    constants::{FLIPNOTE_STUDIO_PUBLIC_KEY, PPM_FORMAT_VERSION},
-    frames::animation_data::PPMAnimationData,
+    audio::{audio_data::PPMAudio, wav_container::WavContainer},
+    constants::{
+        FLIPNOTE_STUDIO_PUBLIC_KEY, PPM_AUDIO_PLAYBACK_SAMPLE_RATE, PPM_FORMAT_VERSION,
+        PPM_MAX_FRAME_COUNT, PPM_TIMESTAMP_EPOCH,
+    },
+    exporters::{
+        self, AnimationExportFormat, AnimationExportOptions,
+        frame_exporter::{self, SpriteSheetOptions},
+        html_exporter,
+        layer_exporter::{self, LayerExportOptions},
+        raw_exporter, svg_exporter,
+        terminal_exporter::{self, TerminalPlayerOptions},
+        video_exporter::{self, VideoExportOptions},
+    },
+    frames::{
+        animation_data::{PPMAnimationData, TimelineFrame},
+        debug_render::{DebugRenderOptions, get_debug_image},
+        frame::PPMFrame,
+        frame_header::{PPMFrameHeader, PPMFrameType},
+        onion_skin::{OnionSkinOptions, OnionSkinRenderer},
+        render_options::RenderOptions,
+    },
+    importers::{
+        ImportOptions, gif_importer, image_sequence_importer,
+        video_importer::{self, VideoImportOptions},
+    },
     parsers::{audio_parser, ppm_parser::ppm_parser},
     thumbnail::PPMThumbnail,
     writers::audio_writer,
@@ -77,6 +101,7 @@ pub struct PPMFile {
 }
 
 impl PPMFile {
+    /// Creates an empty file with no frames or metadata. Use [`PPMFileBuilder`](super::file_builder::PPMFileBuilder) for a flipnote that can be saved.
     pub fn new() -> Self {
         Self {
             format_version: PPM_FORMAT_VERSION,
@@ -100,6 +125,660 @@ impl PPMFile {
         Ok(parsed)
     }
 
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = vec![];
+
+        let mut cursor = std::io::Cursor::new(&mut bytes);
+
+        self.write(&mut cursor)?;
+
+        Ok(bytes)
+    }
+
+    pub fn get_frame_count(&self) -> usize {
+        self.frame_count as usize + 1
+    }
+
+    /// Replaces the animation with the given frames, recomputing the animation section, the header sizes and the sound effect flags.
+    /// Sound effect flags of frames that still exist are kept, new frames start without sound effects.
+    pub fn set_frames(&mut self, frames: &[PPMFrame]) -> Result<()> {
+        ensure!(!frames.is_empty(), "A flipnote needs at least one frame");
+        ensure!(
+            frames.len() <= PPM_MAX_FRAME_COUNT,
+            "A flipnote can have at most {} frames, got {}",
+            PPM_MAX_FRAME_COUNT,
+            frames.len()
+        );
+
+        let animation_data = PPMAnimationData::from_frames(
+            frames,
+            self.animation_data.get_animation_flags().to_owned(),
+        )?;
+
+        self.set_animation_data(animation_data);
+
+        self.audio
+            .audio_header
+            .sound_effect_flags
+            .resize(frames.len(), 0);
+
+        if self.thumbnail_frame_index as usize >= frames.len() {
+            self.thumbnail_frame_index = 0;
+        }
+
+        Ok(())
+    }
+
+    fn set_animation_data(&mut self, animation_data: PPMAnimationData) {
+        let frame_count = animation_data.get_frame_count();
+
+        self.animation_data = animation_data;
+
+        //the header stores the frame count minus one.
+        self.frame_count = (frame_count - 1) as u16;
+        self.animation_data_size = self.animation_data.get_size();
+        self._sound_header_start = (0x6A0 + self.animation_data_size + frame_count as u32) as u64;
+    }
+
+    /// Inserts a frame before `index`, or at the end if `index` is the frame count. The new frame has no sound effects.
+    pub fn insert_frame(&mut self, index: usize, frame: PPMFrame) -> Result<()> {
+        let frame_count = self.get_frame_count();
+
+        ensure!(
+            index <= frame_count,
+            "Cannot insert frame at {}, the flipnote has {} frames",
+            index,
+            frame_count
+        );
+
+        let mut timeline = (0..frame_count)
+            .map(TimelineFrame::Existing)
+            .collect::<Vec<TimelineFrame>>();
+        timeline.insert(index, TimelineFrame::New(frame));
+
+        self.rebuild_timeline(timeline)
+    }
+
+    pub fn remove_frame(&mut self, index: usize) -> Result<()> {
+        let frame_count = self.get_frame_count();
+
+        self.ensure_frame_index(index)?;
+
+        let mut timeline = (0..frame_count)
+            .map(TimelineFrame::Existing)
+            .collect::<Vec<TimelineFrame>>();
+        timeline.remove(index);
+
+        self.rebuild_timeline(timeline)
+    }
+
+    /// Moves the frame at `from` so it ends up at index `to`, together with its sound effects.
+    pub fn move_frame(&mut self, from: usize, to: usize) -> Result<()> {
+        let frame_count = self.get_frame_count();
+
+        self.ensure_frame_index(from)?;
+        self.ensure_frame_index(to)?;
+
+        let mut timeline = (0..frame_count)
+            .map(TimelineFrame::Existing)
+            .collect::<Vec<TimelineFrame>>();
+        let frame = timeline.remove(from);
+        timeline.insert(to, frame);
+
+        self.rebuild_timeline(timeline)
+    }
+
+    /// Inserts a copy of the frame at `index` right after it, including its sound effects.
+    pub fn duplicate_frame(&mut self, index: usize) -> Result<()> {
+        let frame_count = self.get_frame_count();
+
+        self.ensure_frame_index(index)?;
+
+        let mut timeline = (0..frame_count)
+            .map(TimelineFrame::Existing)
+            .collect::<Vec<TimelineFrame>>();
+        timeline.insert(index + 1, TimelineFrame::Existing(index));
+
+        self.rebuild_timeline(timeline)
+    }
+
+    pub fn swap_frames(&mut self, a: usize, b: usize) -> Result<()> {
+        let frame_count = self.get_frame_count();
+
+        self.ensure_frame_index(a)?;
+        self.ensure_frame_index(b)?;
+
+        let mut timeline = (0..frame_count)
+            .map(TimelineFrame::Existing)
+            .collect::<Vec<TimelineFrame>>();
+        timeline.swap(a, b);
+
+        self.rebuild_timeline(timeline)
+    }
+
+    fn ensure_frame_index(&self, index: usize) -> Result<()> {
+        ensure!(
+            index < self.get_frame_count(),
+            "Frame {} is out of range, the flipnote has {} frames",
+            index,
+            self.get_frame_count()
+        );
+
+        Ok(())
+    }
+
+    /// Lays the animation out as `timeline`, carrying the sound effect flags along with their frames and keeping the thumbnail on the same picture.
+    fn rebuild_timeline(&mut self, timeline: Vec<TimelineFrame>) -> Result<()> {
+        ensure!(!timeline.is_empty(), "A flipnote needs at least one frame");
+        ensure!(
+            timeline.len() <= PPM_MAX_FRAME_COUNT,
+            "A flipnote can have at most {} frames, got {}",
+            PPM_MAX_FRAME_COUNT,
+            timeline.len()
+        );
+
+        let sound_effect_flags = timeline
+            .iter()
+            .map(|frame| match frame {
+                TimelineFrame::Existing(index) => self
+                    .audio
+                    .audio_header
+                    .sound_effect_flags
+                    .get(*index)
+                    .copied()
+                    .unwrap_or(0),
+                TimelineFrame::New(_) => 0,
+            })
+            .collect::<Vec<u8>>();
+
+        let thumbnail_frame_index = timeline.iter().position(|frame| {
+            matches!(frame, TimelineFrame::Existing(index) if *index == self.thumbnail_frame_index as usize)
+        });
+
+        let animation_data = self.animation_data.rebuild(&timeline)?;
+
+        self.set_animation_data(animation_data);
+        self.audio.audio_header.sound_effect_flags = sound_effect_flags;
+        self.audio.mixed_tracks = audio_parser::mix_audio(&self.audio)?;
+
+        match thumbnail_frame_index {
+            Some(index) => self.thumbnail_frame_index = index as u16,
+            //the thumbnail's frame is gone, show whatever frame took its place.
+            None => self.set_thumbnail_frame(
+                (self.thumbnail_frame_index as usize).min(timeline.len() - 1),
+            )?,
+        }
+
+        Ok(())
+    }
+
+    /// Appends another flipnote to the end of this one.
+    /// If the speeds differ, the other flipnote's frames are duplicated or dropped to keep its timing at this flipnote's speed.
+    /// The BGM tracks are joined on the timeline, and the other flipnote's sound effects are moved to free slots when both flipnotes use the same slot for different sounds.
+    pub fn append(&mut self, other: &PPMFile) -> Result<()> {
+        let frame_count = self.get_frame_count();
+        let other_frame_count = other.get_frame_count();
+
+        let framerate = self.audio.audio_header.get_framerate()?;
+        let other_framerate = other.audio.audio_header.get_framerate()?;
+
+        let ratio = framerate / other_framerate;
+        let converted_frame_count = ((other_frame_count as f32 * ratio).round() as usize).max(1);
+
+        let (numerator, denominator) = self.audio.audio_header.get_framerate_ratio()?;
+        let (other_numerator, other_denominator) =
+            other.audio.audio_header.get_framerate_ratio()?;
+
+        //the other flipnote's frame shown at converted frame i, worked out in whole numbers so frames and sound effects line up exactly.
+        let get_source_index = |i: usize| {
+            let source_index = i as u64 * other_numerator as u64 * denominator as u64
+                / (other_denominator as u64 * numerator as u64);
+
+            (source_index as usize).min(other_frame_count - 1)
+        };
+
+        ensure!(
+            frame_count + converted_frame_count <= PPM_MAX_FRAME_COUNT,
+            "A flipnote can have at most {} frames, the joined flipnote would have {}",
+            PPM_MAX_FRAME_COUNT,
+            frame_count + converted_frame_count
+        );
+
+        let slot_map = self.merge_sound_effects(other)?;
+
+        let mut sound_effect_flags = self.audio.audio_header.sound_effect_flags.clone();
+        sound_effect_flags.resize(frame_count, 0);
+
+        let mut other_sound_effect_flags = vec![0u8; converted_frame_count];
+
+        for (i, converted_flags) in other_sound_effect_flags.iter_mut().enumerate() {
+            let source_index = get_source_index(i);
+
+            //duplicated frames only play their sound effects the first time.
+            if i > 0 && get_source_index(i - 1) == source_index {
+                continue;
+            }
+
+            //frames that are dropped play theirs on the frame before them.
+            let end = match i + 1 < converted_frame_count {
+                true => get_source_index(i + 1).max(source_index + 1),
+                false => other_frame_count,
+            };
+
+            for flags in other
+                .audio
+                .audio_header
+                .sound_effect_flags
+                .iter()
+                .take(end)
+                .skip(source_index)
+            {
+                for (slot, new_slot) in slot_map.iter().enumerate() {
+                    if flags & (1 << slot) != 0 {
+                        *converted_flags |= 1 << new_slot;
+                    }
+                }
+            }
+        }
+
+        sound_effect_flags.extend(other_sound_effect_flags);
+
+        let background_track = match (&self.audio.background_track, &other.audio.background_track) {
+            (None, None) => None,
+            (background_track, other_background_track) => {
+                let length = (frame_count as f32 / framerate
+                    * PPM_AUDIO_PLAYBACK_SAMPLE_RATE as f32)
+                    .round() as usize;
+
+                let background_track = background_track
+                    .clone()
+                    .unwrap_or_else(|| {
+                        WavContainer::from_samples(vec![], 1, PPM_AUDIO_PLAYBACK_SAMPLE_RATE, 16)
+                    })
+                    .with_length(length);
+
+                match other_background_track {
+                    Some(other_background_track) => {
+                        Some(background_track.concat(other_background_track)?)
+                    }
+                    None => Some(background_track),
+                }
+            }
+        };
+
+        let mut encoded_frames = self.animation_data.get_encoded_frames()?;
+        let mut other_encoded_frames = other.animation_data.get_encoded_frames()?;
+
+        //the first frame is decoded without a predecessor, so it can't stay diffed once it follows our frames.
+        let first_header = PPMFrameHeader {
+            header: other_encoded_frames[0][0],
+        };
+
+        if first_header.get_frame_type() == PPMFrameType::Diffed {
+            other_encoded_frames[0] = other.animation_data.get_frames()?[0].encode()?;
+        }
+
+        encoded_frames.extend(other_encoded_frames);
+
+        let joined_animation_data = PPMAnimationData::from_encoded_frames(
+            &encoded_frames,
+            self.animation_data.get_animation_flags().to_owned(),
+        );
+
+        let timeline = (0..frame_count)
+            .map(TimelineFrame::Existing)
+            .chain(
+                (0..converted_frame_count)
+                    .map(|i| TimelineFrame::Existing(frame_count + get_source_index(i))),
+            )
+            .collect::<Vec<TimelineFrame>>();
+
+        self.set_animation_data(joined_animation_data.rebuild(&timeline)?);
+
+        let speed = self.audio.audio_header.get_speed();
+
+        self.audio.audio_header.sound_effect_flags = sound_effect_flags;
+        self.audio.audio_header.set_bgm_speed(speed)?;
+        self.audio.background_track = background_track;
+        self.audio.mixed_tracks = audio_parser::mix_audio(&self.audio)?;
+
+        Ok(())
+    }
+
+    /// Copies the other flipnote's sound effects into our slots, returning which slot each of its sound effects ended up in.
+    /// Sound effects that are never played are ignored.
+    fn merge_sound_effects(&mut self, other: &PPMFile) -> Result<[usize; 3]> {
+        let mut tracks = [
+            self.audio.sound_effect_1_track.clone(),
+            self.audio.sound_effect_2_track.clone(),
+            self.audio.sound_effect_3_track.clone(),
+        ];
+
+        let other_tracks = [
+            &other.audio.sound_effect_1_track,
+            &other.audio.sound_effect_2_track,
+            &other.audio.sound_effect_3_track,
+        ];
+
+        let is_same_sound = |a: &WavContainer, b: &WavContainer| a.get_samples() == b.get_samples();
+
+        let mut slot_map = [0, 1, 2];
+        let mut conflicts = Vec::new();
+
+        for (slot, other_track) in other_tracks.iter().enumerate() {
+            let is_used = other
+                .audio
+                .audio_header
+                .sound_effect_flags
+                .iter()
+                .any(|flags| flags & (1 << slot) != 0);
+
+            let Some(other_track) = other_track.as_ref().filter(|_| is_used) else {
+                continue;
+            };
+
+            match &tracks[slot] {
+                None => tracks[slot] = Some(other_track.clone()),
+                Some(track) if is_same_sound(track, other_track) => {}
+                Some(_) => conflicts.push((slot, other_track)),
+            }
+        }
+
+        //conflicting sounds go to a slot that is still free, or that already holds the same sound.
+        for (slot, other_track) in conflicts {
+            let new_slot = tracks
+                .iter()
+                .position(|track| match track {
+                    None => true,
+                    Some(track) => is_same_sound(track, other_track),
+                })
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Sound effect {} differs between the flipnotes and there is no free slot to move it to",
+                        slot + 1
+                    )
+                })?;
+
+            tracks[new_slot] = Some(other_track.clone());
+            slot_map[slot] = new_slot;
+        }
+
+        [
+            self.audio.sound_effect_1_track,
+            self.audio.sound_effect_2_track,
+            self.audio.sound_effect_3_track,
+        ] = tracks;
+
+        Ok(slot_map)
+    }
+
+    /// Splits the flipnote before frame `index`, each half keeping its part of the BGM.
+    pub fn split_at(&self, index: usize) -> Result<(PPMFile, PPMFile)> {
+        let frame_count = self.get_frame_count();
+
+        ensure!(
+            index > 0 && index < frame_count,
+            "Cannot split at frame {}, it must be between 1 and {}",
+            index,
+            frame_count - 1
+        );
+
+        let mut first = self.clone();
+        let mut second = self.clone();
+
+        if let Some(background_track) = &self.audio.background_track {
+            let framerate = self.audio.audio_header.get_framerate()?;
+            let split_sample = (index as f32 / framerate
+                * background_track.get_sample_rate() as f32)
+                .round() as usize;
+
+            first.audio.background_track = Some(background_track.slice(0, split_sample));
+            second.audio.background_track = Some(background_track.slice(split_sample, usize::MAX));
+        }
+
+        first.rebuild_timeline((0..index).map(TimelineFrame::Existing).collect())?;
+        second.rebuild_timeline((index..frame_count).map(TimelineFrame::Existing).collect())?;
+
+        Ok((first, second))
+    }
+
+    /// Creates a new flipnote from a list of images, see [`image_sequence_importer::import_image_sequence`].
+    pub fn from_image_sequence(
+        paths: &[PathBuf],
+        framerate: f32,
+        options: &ImportOptions,
+    ) -> Result<Self> {
+        image_sequence_importer::import_image_sequence(paths, framerate, options)
+    }
+
+    /// Creates a new flipnote from every PNG or JPEG image in a directory, ordered by file name.
+    pub fn from_image_directory(
+        path: impl Into<PathBuf>,
+        framerate: f32,
+        options: &ImportOptions,
+    ) -> Result<Self> {
+        let paths = image_sequence_importer::get_image_paths(path)?;
+
+        image_sequence_importer::import_image_sequence(&paths, framerate, options)
+    }
+
+    /// Creates a new flipnote from an animated GIF, see [`gif_importer::import_gif`].
+    pub fn from_gif(
+        path: impl Into<PathBuf>,
+        framerate: Option<f32>,
+        options: &ImportOptions,
+    ) -> Result<Self> {
+        let bytes = std::fs::read(path.into())?;
+
+        gif_importer::import_gif(&bytes, framerate, options)
+    }
+
+    /// Creates a new flipnote from a video using ffmpeg, see [`video_importer::import_video`].
+    pub fn from_video(
+        path: impl Into<PathBuf>,
+        framerate: f32,
+        video_options: &VideoImportOptions,
+        options: &ImportOptions,
+    ) -> Result<Self> {
+        video_importer::import_video(path, framerate, video_options, options)
+    }
+
+    pub fn get_root_author_name(&self) -> String {
+        decode_name(&self.root_name_buf)
+    }
+
+    pub fn get_parent_author_name(&self) -> String {
+        decode_name(&self.parent_name_buf)
+    }
+
+    pub fn get_current_author_name(&self) -> String {
+        decode_name(&self.child_name_buf)
+    }
+
+    /// Sets the root, parent and current author names, as Flipnote Studio does for a brand-new flipnote.
+    pub fn set_author_name(&mut self, name: &str) -> Result<()> {
+        let name_buf = encode_name(name)?;
+
+        self.root_name_buf = name_buf;
+        self.parent_name_buf = name_buf;
+        self.child_name_buf = name_buf;
+
+        Ok(())
+    }
+
+    pub fn get_root_id(&self) -> u64 {
+        self.root_id
+    }
+
+    pub fn get_parent_id(&self) -> u64 {
+        self.parent_id
+    }
+
+    pub fn get_current_id(&self) -> u64 {
+        self.current_id
+    }
+
+    /// Sets the root, parent and current author FSIDs, as Flipnote Studio does for a brand-new flipnote.
+    pub fn set_author_id(&mut self, fsid: u64) {
+        self.root_id = fsid;
+        self.parent_id = fsid;
+        self.current_id = fsid;
+    }
+
+    /// Returns the file name without extension, e.g. `F78DA8_14FB9A4A5C8F3_000`.
+    pub fn get_current_file_name(&self) -> String {
+        decode_file_name(&self.current_file_name_buf)
+    }
+
+    pub fn get_parent_file_name(&self) -> String {
+        decode_file_name(&self.parent_file_name_buf)
+    }
+
+    /// Gives the flipnote a new random file name, as Flipnote Studio does when a brand-new flipnote is saved.
+    /// The file name starts with the last 3 bytes of the current author's FSID, so call [`PPMFile::set_author_id`] first.
+    pub fn generate_file_name(&mut self, rng: &mut impl RngCore) {
+        let mut file_name_buf = [0u8; 18];
+
+        //the MAC address part is stored big endian.
+        let mac = &self.current_id.to_le_bytes()[0..3];
+        file_name_buf[0] = mac[2];
+        file_name_buf[1] = mac[1];
+        file_name_buf[2] = mac[0];
+
+        for c in file_name_buf[3..16].iter_mut() {
+            *c = b"0123456789ABCDEF"[(rng.next_u32() % 16) as usize];
+        }
+
+        //the root fragment packs the first 10 characters as hex digits.
+        let mut root_file_fragment_buf = [0u8; 8];
+        root_file_fragment_buf[0..3].copy_from_slice(&file_name_buf[0..3]);
+
+        for (i, digits) in file_name_buf[3..13].chunks_exact(2).enumerate() {
+            let high = (digits[0] as char).to_digit(16).unwrap_or(0) as u8;
+            let low = (digits[1] as char).to_digit(16).unwrap_or(0) as u8;
+
+            root_file_fragment_buf[3 + i] = (high << 4) | low;
+        }
+
+        self.parent_file_name_buf = file_name_buf;
+        self.current_file_name_buf = file_name_buf;
+        self.root_file_fragment_buf = root_file_fragment_buf;
+    }
+
+    pub fn get_locked(&self) -> bool {
+        self.locked_buf != 0
+    }
+
+    pub fn set_locked(&mut self, value: bool) {
+        self.locked_buf = value as u16;
+    }
+
+    pub fn get_timestamp(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(PPM_TIMESTAMP_EPOCH + self.time_stamp_buf as u64)
+    }
+
+    pub fn set_timestamp(&mut self, time: SystemTime) -> Result<()> {
+        let seconds = time.duration_since(UNIX_EPOCH)?.as_secs();
+
+        ensure!(
+            seconds >= PPM_TIMESTAMP_EPOCH,
+            "Timestamps before the year 2000 can't be stored"
+        );
+
+        self.time_stamp_buf = (seconds - PPM_TIMESTAMP_EPOCH) as u32;
+
+        Ok(())
+    }
+
+    pub fn get_thumbnail_frame_index(&self) -> usize {
+        self.thumbnail_frame_index as usize
+    }
+
+    /// Sets which frame the thumbnail shows, and renders the thumbnail from it.
+    pub fn set_thumbnail_frame(&mut self, index: usize) -> Result<()> {
+        let frames = self.animation_data.get_frames()?;
+
+        ensure!(
+            index < frames.len(),
+            "Thumbnail frame {} is out of range, the flipnote has {} frames",
+            index,
+            frames.len()
+        );
+
+        self.thumbnail.set_image(&frames[index].get_image()?)?;
+        self.thumbnail_frame_index = index as u16;
+
+        Ok(())
+    }
+
+    /// Replaces the signature with zeroes. Files without a valid signature can be read, but not played back by Flipnote Studio until signed with [`PPMFile::sign`].
+    pub fn clear_signature(&mut self) {
+        self.signature = vec![0; 0x80];
+    }
+
+    /// Checks that the sizes, counts and values in the file are consistent, so it can be written and read back.
+    /// This does not check the signature, see [`PPMFile::verify_signature`].
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.format_version == PPM_FORMAT_VERSION,
+            "Unknown format version {:#X}",
+            self.format_version
+        );
+
+        let frame_count = self.get_frame_count();
+
+        ensure!(
+            frame_count <= PPM_MAX_FRAME_COUNT,
+            "A flipnote can have at most {} frames, got {}",
+            PPM_MAX_FRAME_COUNT,
+            frame_count
+        );
+        ensure!(
+            self.animation_data.get_frame_count() == frame_count,
+            "The header has {} frames, but the animation has {}",
+            frame_count,
+            self.animation_data.get_frame_count()
+        );
+        ensure!(
+            self.animation_data_size == self.animation_data.get_size(),
+            "The header says the animation is {} bytes, but it is {}",
+            self.animation_data_size,
+            self.animation_data.get_size()
+        );
+
+        self.animation_data.get_frames()?;
+
+        ensure!(
+            self.audio.audio_header.sound_effect_flags.len() == frame_count,
+            "There are {} sound effect flags for {} frames",
+            self.audio.audio_header.sound_effect_flags.len(),
+            frame_count
+        );
+        ensure!(
+            (self.thumbnail_frame_index as usize) < frame_count,
+            "Thumbnail frame {} is out of range, the flipnote has {} frames",
+            self.thumbnail_frame_index,
+            frame_count
+        );
+        ensure!(self.thumbnail.is_complete(), "The thumbnail is incomplete");
+        ensure!(
+            self.locked_buf <= 1,
+            "Invalid lock state {}",
+            self.locked_buf
+        );
+
+        self.audio.audio_header.get_framerate()?;
+        self.audio.audio_header.get_bgm_framerate()?;
+
+        ensure!(
+            self.signature.len() == 0x80,
+            "The signature must be 0x80 bytes long, got {:#X}",
+            self.signature.len()
+        );
+
+        Ok(())
+    }
+
     pub fn save_as(&self, path: impl Into<PathBuf>) -> Result<()> {
         let mut path: PathBuf = path.into();
 
@@ -185,79 +864,448 @@ impl PPMFile {
         Ok(())
     }
 
-    pub fn export_video(&self, path: impl Into<PathBuf>, audio_sample_rate: i32) -> Result<()> {
-        // use the ffmpeg crate to encode the video. for now we only want to render the frames.
+    /// Exports the animation as a GIF without needing ffmpeg, see [`exporters::gif_exporter::write_gif`].
+    pub fn export_gif(&self, path: impl Into<PathBuf>) -> Result<()> {
+        self.export_animation(
+            path,
+            AnimationExportFormat::Gif,
+            &AnimationExportOptions::default(),
+        )
+    }
+
+    /// Exports the animation as a GIF, APNG or animated WebP, playing at the flipnote's speed and looping if it loops.
+    pub fn export_animation(
+        &self,
+        path: impl Into<PathBuf>,
+        format: AnimationExportFormat,
+        options: &AnimationExportOptions,
+    ) -> Result<()> {
+        let file = File::create(path.into())?;
+
+        exporters::write_animation(self, BufWriter::new(file), format, options)
+    }
+
+    /// Saves every frame as a numbered image, see [`frame_exporter::export_frames`].
+    pub fn export_frames(
+        &self,
+        dir: impl Into<PathBuf>,
+        pattern: &str,
+        options: &RenderOptions,
+    ) -> Result<Vec<PathBuf>> {
+        frame_exporter::export_frames(self, dir, pattern, options)
+    }
 
+    /// Renders a frame over tinted ghosts of the frames around it, see [`OnionSkinRenderer`].
+    /// Use the renderer directly when rendering many frames, it reuses the frames it already rendered.
+    pub fn get_onion_skin_image(
+        &self,
+        index: usize,
+        options: &OnionSkinOptions,
+    ) -> Result<ImageWrapper> {
         let frames = self.animation_data.get_frames()?;
 
-        let framerate = self.audio.audio_header.get_framerate()?;
+        OnionSkinRenderer::new(&frames, options.clone())?.get_image(index)
+    }
 
-        let path: PathBuf = path.into();
+    /// Saves every frame with onion skinning as a numbered image, see [`frame_exporter::export_onion_skin_frames`].
+    pub fn export_onion_skin_frames(
+        &self,
+        dir: impl Into<PathBuf>,
+        pattern: &str,
+        options: &OnionSkinOptions,
+    ) -> Result<Vec<PathBuf>> {
+        frame_exporter::export_onion_skin_frames(self, dir, pattern, options)
+    }
 
-        unsafe {
-            libc::mkfifo("audio".as_ptr() as *const i8, 0o777);
-            libc::mkfifo("video".as_ptr() as *const i8, 0o777);
-        };
+    /// Renders how a frame is encoded, see [`get_debug_image`].
+    pub fn get_debug_image(
+        &self,
+        index: usize,
+        options: &DebugRenderOptions,
+    ) -> Result<ImageWrapper> {
+        let frames = self.animation_data.get_frames()?;
 
-        let mut audio_file = File::create("audio")?;
-        let mut stdin = File::create("video")?;
+        ensure!(index < frames.len(), "Frame index {} out of bounds", index);
 
-        let audio = self
-            .audio
-            .mixed_tracks
-            .to_owned()
-            .unwrap_or_default()
-            .resample(audio_sample_rate)?;
+        let previous = index.checked_sub(1).map(|previous| &frames[previous]);
 
-        let wav_data = audio
-            .get_samples()
-            .iter()
-            .flat_map(|s| s.to_le_bytes())
-            .collect::<Vec<u8>>();
+        get_debug_image(&frames[index], previous, options)
+    }
+
+    /// Saves the debug image of every frame as a numbered image, see [`frame_exporter::export_debug_frames`].
+    pub fn export_debug_frames(
+        &self,
+        dir: impl Into<PathBuf>,
+        pattern: &str,
+        options: &DebugRenderOptions,
+    ) -> Result<Vec<PathBuf>> {
+        frame_exporter::export_debug_frames(self, dir, pattern, options)
+    }
+
+    /// Packs every frame into a grid with a JSON description next to it, see [`frame_exporter::export_sprite_sheet`].
+    pub fn export_sprite_sheet(
+        &self,
+        path: impl Into<PathBuf>,
+        options: &SpriteSheetOptions,
+    ) -> Result<()> {
+        frame_exporter::export_sprite_sheet(self, path, options)
+    }
+
+    /// Saves both layers of every frame as separate transparent images or masks, see [`layer_exporter::export_layers`].
+    pub fn export_layers(
+        &self,
+        dir: impl Into<PathBuf>,
+        pattern: &str,
+        options: &LayerExportOptions,
+    ) -> Result<[Vec<PathBuf>; 2]> {
+        layer_exporter::export_layers(self, dir, pattern, options)
+    }
+
+    /// Saves every frame as an SVG file, see [`svg_exporter::export_svg_frames`].
+    pub fn export_svg_frames(
+        &self,
+        dir: impl Into<PathBuf>,
+        pattern: &str,
+        options: &RenderOptions,
+    ) -> Result<Vec<PathBuf>> {
+        svg_exporter::export_svg_frames(self, dir, pattern, options)
+    }
+
+    /// Exports the animation as a single animated SVG, see [`svg_exporter::write_animated_svg`].
+    pub fn export_animated_svg(
+        &self,
+        path: impl Into<PathBuf>,
+        options: &RenderOptions,
+    ) -> Result<()> {
+        let file = File::create(path.into())?;
+
+        svg_exporter::write_animated_svg(self, BufWriter::new(file), options)
+    }
+
+    /// Exports a single web page that plays the flipnote, see [`html_exporter::write_html`].
+    pub fn export_html(&self, path: impl Into<PathBuf>, options: &RenderOptions) -> Result<()> {
+        let file = File::create(path.into())?;
+
+        html_exporter::write_html(self, BufWriter::new(file), options)
+    }
+
+    /// Streams the animation as Y4M, e.g. to pipe into an encoder. See [`raw_exporter::write_y4m`].
+    pub fn write_y4m<W: Write>(&self, writer: W, options: &RenderOptions) -> Result<()> {
+        raw_exporter::write_y4m(self, writer, options)
+    }
+
+    /// Streams the animation as headerless RGBA frames, see [`raw_exporter::write_rawvideo`].
+    pub fn write_rawvideo<W: Write>(&self, writer: W, options: &RenderOptions) -> Result<()> {
+        raw_exporter::write_rawvideo(self, writer, options)
+    }
+
+    /// Streams the mixed audio as s16le samples matching the video streams, see [`raw_exporter::write_s16le`].
+    pub fn write_s16le<W: Write>(&self, writer: W, sample_rate: i32, channels: u16) -> Result<()> {
+        raw_exporter::write_s16le(self, writer, sample_rate, channels)
+    }
+
+    /// Plays the animation in the terminal with keyboard controls, see [`terminal_exporter::play_in_terminal`].
+    pub fn play_in_terminal(&self, options: &TerminalPlayerOptions) -> Result<()> {
+        terminal_exporter::play_in_terminal(self, options)
+    }
+
+    /// Encodes the animation and its audio into a video with ffmpeg, see [`video_exporter::export_video`].
+    pub fn export_video(
+        &self,
+        path: impl Into<PathBuf>,
+        options: &VideoExportOptions,
+    ) -> Result<()> {
+        video_exporter::export_video(self, path, options)
+    }
+}
+
+fn decode_name(name_buf: &[u8; 22]) -> String {
+    let name = name_buf
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|c| *c != 0)
+        .collect::<Vec<u16>>();
+
+    String::from_utf16_lossy(&name)
+}
+
+fn decode_file_name(file_name_buf: &[u8; 18]) -> String {
+    format!(
+        "{:02X}{:02X}{:02X}_{}_{:03}",
+        file_name_buf[0],
+        file_name_buf[1],
+        file_name_buf[2],
+        String::from_utf8_lossy(&file_name_buf[3..16]),
+        u16::from_le_bytes([file_name_buf[16], file_name_buf[17]])
+    )
+}
+
+fn encode_name(name: &str) -> Result<[u8; 22]> {
+    let name = name.encode_utf16().collect::<Vec<u16>>();
+
+    ensure!(
+        name.len() <= 11,
+        "Author names can be at most 11 characters long"
+    );
+
+    let mut name_buf = [0u8; 22];
+
+    for (i, c) in name.iter().enumerate() {
+        name_buf[i * 2..i * 2 + 2].copy_from_slice(&c.to_le_bytes());
+    }
+
+    Ok(name_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ppm::file_builder::PPMFileBuilder, utils::test_utils::get_test_frame};
+
+    fn get_test_file(frame_count: usize, speed: u8, sound_effect_flags: Vec<u8>) -> PPMFile {
+        PPMFileBuilder::new()
+            .frames((0..frame_count).map(get_test_frame).collect())
+            .speed(speed)
+            .sound_effect_flags(sound_effect_flags)
+            .seed(1)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn append_at_a_faster_speed_keeps_sound_effects_on_their_frames() {
+        //20 fps, so the 12 fps frames of the other flipnote are shown 5 times for every 3.
+        let mut file = get_test_file(2, 7, vec![0, 0]);
+        let other = get_test_file(5, 6, vec![0b001, 0b010, 0b100, 0b001, 0b010]);
 
-        audio_file.write_all(&wav_data)?;
-
-        frames.iter().try_for_each(|f| {
-            let image = f.get_image()?;
-
-            let buffer = image.get_raw_pixels();
-
-            stdin.write_all(&buffer)?;
-
-            Result::<_, anyhow::Error>::Ok(())
-        })?;
-
-        //ffmpeg command preparation
-        let ffmpeg = Command::new("ffmpeg")
-            .arg("-y")
-            .args(["-f", "rawvideo"])
-            .args(["-pix_fmt", "rgba"])
-            .args(["-video_size", "256x192"])
-            .arg("-framerate")
-            .arg((framerate as i32).to_string())
-            .args(["-i", "video"])
-            .args([
-                "-f",
-                "s16le",
-                "-sample_rate",
-                &audio_sample_rate.to_string(),
-            ])
-            .args(["-i", "audio"])
-            .args(["-c:v", "libx264"])
-            .arg(path.to_string_lossy().to_string())
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()?;
-
-        let output = ffmpeg.wait_with_output()?;
-
-        Command::new("rm").arg("audio").arg("video").spawn()?;
-
-        if !output.status.success() {
-            bail!("ffmpeg failed: {:?}", output.status);
+        file.append(&other).unwrap();
+
+        let frames = file.animation_data.get_frames().unwrap();
+        let other_frames = other.animation_data.get_frames().unwrap();
+
+        //converted frames show other frames 0, 0, 1, 1, 2, 3, 3 and 4.
+        let source_indexes = [0, 0, 1, 1, 2, 3, 3, 4];
+
+        assert_eq!(frames.len(), 2 + source_indexes.len());
+
+        for (frame, source_index) in frames[2..].iter().zip(source_indexes) {
+            assert_eq!(
+                frame.get_indexed_pixels().unwrap(),
+                other_frames[source_index].get_indexed_pixels().unwrap()
+            );
         }
 
-        Ok(())
+        //every sound effect plays once, on the first frame that shows its frame.
+        assert_eq!(
+            file.audio.audio_header.sound_effect_flags,
+            [0, 0, 0b001, 0, 0b010, 0, 0b100, 0b001, 0, 0b010]
+        );
+    }
+
+    #[test]
+    fn append_at_a_slower_speed_moves_sound_effects_of_dropped_frames() {
+        //12 fps, so 3 of every 5 frames of the 20 fps flipnote are kept.
+        let mut file = get_test_file(1, 6, vec![0]);
+        let other = get_test_file(5, 7, vec![0b001, 0b010, 0b100, 0b001, 0b010]);
+
+        file.append(&other).unwrap();
+
+        let frames = file.animation_data.get_frames().unwrap();
+        let other_frames = other.animation_data.get_frames().unwrap();
+
+        //converted frames show other frames 0, 1 and 3.
+        for (frame, source_index) in frames[1..].iter().zip([0, 1, 3]) {
+            assert_eq!(
+                frame.get_indexed_pixels().unwrap(),
+                other_frames[source_index].get_indexed_pixels().unwrap()
+            );
+        }
+
+        assert_eq!(
+            file.audio.audio_header.sound_effect_flags,
+            [0, 0b001, 0b110, 0b011]
+        );
+    }
+
+    /// A flipnote of `sound_effect_flags.len()` test frames at 12 fps, with a sound effect of 64 copies of the given sample in each given slot.
+    fn get_sound_effect_file(sound_effects: &[(u8, i16)], sound_effect_flags: Vec<u8>) -> PPMFile {
+        let mut builder = PPMFileBuilder::new()
+            .frames((0..sound_effect_flags.len()).map(get_test_frame).collect())
+            .speed(6)
+            .sound_effect_flags(sound_effect_flags)
+            .seed(1);
+
+        for (slot, sample) in sound_effects {
+            let track = WavContainer::from_samples(
+                vec![*sample; 64],
+                1,
+                PPM_AUDIO_PLAYBACK_SAMPLE_RATE,
+                16,
+            );
+
+            builder = builder.sound_effect(*slot, track).unwrap();
+        }
+
+        builder.build().unwrap()
+    }
+
+    fn get_sound_effect_samples(file: &PPMFile) -> [Option<i16>; 3] {
+        [
+            &file.audio.sound_effect_1_track,
+            &file.audio.sound_effect_2_track,
+            &file.audio.sound_effect_3_track,
+        ]
+        .map(|track| track.as_ref().map(|track| track.get_samples()[0]))
+    }
+
+    #[test]
+    fn append_moves_different_sounds_in_the_same_slot() {
+        let mut file = get_sound_effect_file(&[(1, 100)], vec![0b001, 0]);
+        //slot 1 holds a different sound, slot 2 a new one and slot 3 is never played.
+        let other =
+            get_sound_effect_file(&[(1, 200), (2, 300), (3, 400)], vec![0b011, 0b001, 0b010]);
+
+        file.append(&other).unwrap();
+
+        //the other slot 1 moves to slot 3, the only one left free.
+        assert_eq!(
+            get_sound_effect_samples(&file),
+            [Some(100), Some(300), Some(200)]
+        );
+        assert_eq!(
+            file.audio.audio_header.sound_effect_flags,
+            [0b001, 0, 0b110, 0b100, 0b010]
+        );
+
+        //the same sound in the same slot stays where it is.
+        let mut file = get_sound_effect_file(&[(1, 100)], vec![0b001]);
+        file.append(&get_sound_effect_file(&[(1, 100)], vec![0b001]))
+            .unwrap();
+
+        assert_eq!(get_sound_effect_samples(&file), [Some(100), None, None]);
+        assert_eq!(file.audio.audio_header.sound_effect_flags, [0b001, 0b001]);
+
+        //3 different sounds in use leave no room for a 4th.
+        let mut file = get_sound_effect_file(&[(1, 100), (2, 300), (3, 400)], vec![0b111]);
+        let other = get_sound_effect_file(&[(1, 200)], vec![0b001]);
+
+        assert!(file.append(&other).is_err());
+    }
+
+    #[test]
+    fn split_and_append_give_back_the_flipnote() {
+        let flags = vec![0b001, 0b010, 0, 0b100, 0b011];
+        let file = get_sound_effect_file(&[(1, 100), (2, 200), (3, 300)], flags.clone());
+
+        let (mut first, second) = file.split_at(2).unwrap();
+
+        assert_eq!(first.get_frame_count(), 2);
+        assert_eq!(second.get_frame_count(), 3);
+        assert_eq!(first.audio.audio_header.sound_effect_flags, flags[..2]);
+        assert_eq!(second.audio.audio_header.sound_effect_flags, flags[2..]);
+
+        first.append(&second).unwrap();
+
+        assert_timeline(&first, &[0, 1, 2, 3, 4], &flags);
+        assert_eq!(
+            get_sound_effect_samples(&first),
+            [Some(100), Some(200), Some(300)]
+        );
+
+        assert!(file.split_at(0).is_err());
+        assert!(file.split_at(5).is_err());
+    }
+
+    /// Checks the frames against the test frames of `seeds`, and that the header sizes still describe the file.
+    fn assert_timeline(file: &PPMFile, seeds: &[usize], sound_effect_flags: &[u8]) {
+        file.validate().unwrap();
+
+        assert_eq!(file.get_frame_count(), seeds.len());
+        assert_eq!(file.animation_data_size, file.animation_data.get_size());
+        assert_eq!(
+            file.audio.audio_header.sound_effect_flags,
+            sound_effect_flags
+        );
+
+        let read = PPMFile::from_bytes(&file.to_bytes().unwrap()).unwrap();
+        let frames = read.animation_data.get_frames().unwrap();
+
+        assert_eq!(
+            read.audio.audio_header.sound_effect_flags,
+            sound_effect_flags
+        );
+        assert_eq!(frames.len(), seeds.len());
+
+        for (frame, seed) in frames.iter().zip(seeds) {
+            assert_eq!(
+                frame.get_indexed_pixels().unwrap(),
+                get_test_frame(*seed).get_indexed_pixels().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn timeline_edits_keep_sizes_and_sound_effects() {
+        let mut file = get_test_file(4, 4, vec![0b001, 0b010, 0b100, 0b011]);
+        //the thumbnail follows the picture of seed 2.
+        file.set_thumbnail_frame(2).unwrap();
+
+        assert_timeline(&file, &[0, 1, 2, 3], &[0b001, 0b010, 0b100, 0b011]);
+
+        file.insert_frame(1, get_test_frame(7)).unwrap();
+        assert_timeline(&file, &[0, 7, 1, 2, 3], &[0b001, 0, 0b010, 0b100, 0b011]);
+        assert_eq!(file.get_thumbnail_frame_index(), 3);
+
+        file.duplicate_frame(2).unwrap();
+        assert_timeline(
+            &file,
+            &[0, 7, 1, 1, 2, 3],
+            &[0b001, 0, 0b010, 0b010, 0b100, 0b011],
+        );
+        assert_eq!(file.get_thumbnail_frame_index(), 4);
+
+        file.move_frame(0, 4).unwrap();
+        assert_timeline(
+            &file,
+            &[7, 1, 1, 2, 0, 3],
+            &[0, 0b010, 0b010, 0b100, 0b001, 0b011],
+        );
+        assert_eq!(file.get_thumbnail_frame_index(), 3);
+
+        file.swap_frames(0, 5).unwrap();
+        assert_timeline(
+            &file,
+            &[3, 1, 1, 2, 0, 7],
+            &[0b011, 0b010, 0b010, 0b100, 0b001, 0],
+        );
+        assert_eq!(file.get_thumbnail_frame_index(), 3);
+
+        file.remove_frame(2).unwrap();
+        assert_timeline(&file, &[3, 1, 2, 0, 7], &[0b011, 0b010, 0b100, 0b001, 0]);
+        assert_eq!(file.get_thumbnail_frame_index(), 2);
+
+        assert!(file.remove_frame(5).is_err());
+        assert!(file.insert_frame(6, get_test_frame(0)).is_err());
+
+        //removing the thumbnail's picture shows the frame that took its place.
+        file.remove_frame(2).unwrap();
+        assert_timeline(&file, &[3, 1, 0, 7], &[0b011, 0b010, 0b001, 0]);
+        assert_eq!(file.get_thumbnail_frame_index(), 2);
+    }
+
+    #[test]
+    fn timeline_edits_stop_at_the_frame_limit() {
+        let mut file = get_test_file(1, 4, vec![0]);
+
+        //encoding 999 frames one by one is slow, they are all the same empty frame.
+        let encoded_frames = vec![PPMFrame::default().encode().unwrap(); PPM_MAX_FRAME_COUNT];
+        file.set_animation_data(PPMAnimationData::from_encoded_frames(
+            &encoded_frames,
+            file.animation_data.get_animation_flags().to_owned(),
+        ));
+
+        assert!(file.insert_frame(0, PPMFrame::default()).is_err());
+        assert!(file.duplicate_frame(0).is_err());
+        assert_eq!(file.get_frame_count(), PPM_MAX_FRAME_COUNT);
     }
 }
@@ -0,0 +1,256 @@
+//! Builder for brand-new flipnotes that Flipnote Studio accepts once signed.
+
+use std::time::SystemTime;
+
+use anyhow::{Result, ensure};
+use rand::{RngCore, SeedableRng, rngs::StdRng};
+
+use super::{
+    audio::wav_container::WavContainer,
+    constants::PPM_AUDIO_PLAYBACK_SAMPLE_RATE,
+    file::PPMFile,
+    frames::{animation_flags::PPMAnimationFlags, frame::PPMFrame},
+    parsers::audio_parser,
+};
+
+#[derive(Debug, Clone)]
+pub struct PPMFileBuilder {
+    author_name: String,
+    fsid: Option<u64>,
+    frames: Vec<PPMFrame>,
+    speed: u8,
+    loop_animation: bool,
+    background_track: Option<WavContainer>,
+    sound_effect_tracks: [Option<WavContainer>; 3],
+    sound_effect_flags: Option<Vec<u8>>,
+    thumbnail_frame_index: usize,
+    timestamp: Option<SystemTime>,
+    seed: Option<u64>,
+}
+
+impl Default for PPMFileBuilder {
+    fn default() -> Self {
+        Self {
+            author_name: String::from("Paracule"),
+            fsid: None,
+            frames: vec![],
+            speed: 4,
+            loop_animation: false,
+            background_track: None,
+            sound_effect_tracks: [None, None, None],
+            sound_effect_flags: None,
+            thumbnail_frame_index: 0,
+            timestamp: None,
+            seed: None,
+        }
+    }
+}
+
+impl PPMFileBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// At most 11 characters.
+    pub fn author_name(mut self, name: impl Into<String>) -> Self {
+        self.author_name = name.into();
+        self
+    }
+
+    /// The author's Flipnote Studio ID. A random one is generated if not set.
+    pub fn fsid(mut self, fsid: u64) -> Self {
+        self.fsid = Some(fsid);
+        self
+    }
+
+    pub fn frames(mut self, frames: Vec<PPMFrame>) -> Self {
+        self.frames = frames;
+        self
+    }
+
+    pub fn frame(mut self, frame: PPMFrame) -> Self {
+        self.frames.push(frame);
+        self
+    }
+
+    /// Playback speed from 1 (0.5 fps) to 8 (30 fps), 4 by default.
+    pub fn speed(mut self, speed: u8) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn loop_animation(mut self, value: bool) -> Self {
+        self.loop_animation = value;
+        self
+    }
+
+    /// The BGM, played from the first frame.
+    pub fn background_track(mut self, track: WavContainer) -> Self {
+        self.background_track = Some(track);
+        self
+    }
+
+    /// Sets the sound effect in slot 1, 2 or 3. Use [`PPMFileBuilder::sound_effect_flags`] to choose the frames that play it.
+    pub fn sound_effect(mut self, slot: u8, track: WavContainer) -> Result<Self> {
+        ensure!(slot > 0 && slot <= 3, "Sound effect slot must be 1, 2 or 3");
+
+        self.sound_effect_tracks[slot as usize - 1] = Some(track);
+
+        Ok(self)
+    }
+
+    /// One byte per frame, bit 0 to 2 play sound effects 1 to 3 on that frame.
+    pub fn sound_effect_flags(mut self, flags: Vec<u8>) -> Self {
+        self.sound_effect_flags = Some(flags);
+        self
+    }
+
+    /// The frame the thumbnail is rendered from.
+    pub fn thumbnail_frame_index(mut self, index: usize) -> Self {
+        self.thumbnail_frame_index = index;
+        self
+    }
+
+    /// The time the flipnote was saved, the time of building if not set.
+    pub fn timestamp(mut self, time: SystemTime) -> Self {
+        self.timestamp = Some(time);
+        self
+    }
+
+    /// Seeds the generator for the file name and FSID, so building the same flipnote twice gives the same bytes.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Builds an unsigned flipnote, see [`PPMFile::sign`].
+    pub fn build(self) -> Result<PPMFile> {
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        let mut file = PPMFile::new();
+
+        let mut flags = PPMAnimationFlags::new();
+        flags.set_loop(self.loop_animation);
+        *file.animation_data.get_animation_flags_mut() = flags;
+
+        file.set_frames(&self.frames)?;
+
+        if let Some(sound_effect_flags) = self.sound_effect_flags {
+            ensure!(
+                sound_effect_flags.len() == self.frames.len(),
+                "There are {} sound effect flags for {} frames",
+                sound_effect_flags.len(),
+                self.frames.len()
+            );
+            ensure!(
+                sound_effect_flags.iter().all(|flags| *flags <= 0b111),
+                "Sound effect flags can only use bits 0 to 2"
+            );
+
+            file.audio.audio_header.sound_effect_flags = sound_effect_flags;
+        }
+
+        file.audio.audio_header.set_speed(self.speed)?;
+        file.audio.audio_header.set_bgm_speed(self.speed)?;
+
+        //decoded tracks are kept at the playback rate, the writer converts them back.
+        let resample = |track: Option<WavContainer>| {
+            track
+                .map(|track| track.resample(PPM_AUDIO_PLAYBACK_SAMPLE_RATE))
+                .transpose()
+        };
+
+        let [
+            sound_effect_1_track,
+            sound_effect_2_track,
+            sound_effect_3_track,
+        ] = self.sound_effect_tracks;
+
+        file.audio.background_track = resample(self.background_track)?;
+        file.audio.sound_effect_1_track = resample(sound_effect_1_track)?;
+        file.audio.sound_effect_2_track = resample(sound_effect_2_track)?;
+        file.audio.sound_effect_3_track = resample(sound_effect_3_track)?;
+        file.audio.mixed_tracks = audio_parser::mix_audio(&file.audio)?;
+
+        let fsid = self.fsid.unwrap_or_else(|| rng.next_u64());
+
+        file.set_author_name(&self.author_name)?;
+        file.set_author_id(fsid);
+        file.generate_file_name(&mut rng);
+        file.set_timestamp(self.timestamp.unwrap_or_else(SystemTime::now))?;
+        file.set_thumbnail_frame(self.thumbnail_frame_index)?;
+        file.clear_signature();
+
+        file.validate()?;
+
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::utils::test_utils::get_test_frame;
+
+    #[test]
+    fn build_round_trips_through_bytes() {
+        let frames = (0..4).map(get_test_frame).collect::<Vec<_>>();
+        let timestamp = UNIX_EPOCH + Duration::from_secs(1_300_000_000);
+
+        let file = PPMFileBuilder::new()
+            .author_name("Tester")
+            .fsid(0x1234_5678_9ABC_DEF0)
+            .frames(frames.clone())
+            .speed(6)
+            .loop_animation(true)
+            .sound_effect_flags(vec![0b001, 0, 0b110, 0b111])
+            .thumbnail_frame_index(2)
+            .timestamp(timestamp)
+            .seed(1)
+            .build()
+            .unwrap();
+
+        let read = PPMFile::from_bytes(&file.to_bytes().unwrap()).unwrap();
+
+        read.validate().unwrap();
+
+        let read_frames = read.animation_data.get_frames().unwrap();
+
+        assert_eq!(read_frames.len(), frames.len());
+
+        for (read_frame, frame) in read_frames.iter().zip(frames.iter()) {
+            assert_eq!(
+                read_frame.get_header().get_paper_color(),
+                frame.get_header().get_paper_color()
+            );
+            assert_eq!(
+                read_frame.get_indexed_pixels().unwrap(),
+                frame.get_indexed_pixels().unwrap()
+            );
+        }
+
+        assert!(read.animation_data.get_animation_flags().get_loop());
+        assert_eq!(read.get_current_author_name(), "Tester");
+        assert_eq!(read.get_root_author_name(), "Tester");
+        assert_eq!(read.get_current_id(), 0x1234_5678_9ABC_DEF0);
+        assert_eq!(read.get_current_file_name(), file.get_current_file_name());
+        assert_eq!(read.get_timestamp(), timestamp);
+        assert_eq!(read.get_thumbnail_frame_index(), 2);
+        assert_eq!(read.audio.audio_header.get_speed(), 6);
+        assert_eq!(read.audio.audio_header.get_bgm_speed(), 6);
+        assert_eq!(
+            read.audio.audio_header.sound_effect_flags,
+            vec![0b001, 0, 0b110, 0b111]
+        );
+        assert!(read.thumbnail.is_complete());
+        assert_eq!(
+            read.thumbnail.get_image().unwrap().get_pixels().unwrap(),
+            file.thumbnail.get_image().unwrap().get_pixels().unwrap()
+        );
+    }
+}
@@ -1,9 +1,22 @@
 use std::io::{Cursor, Seek};
 
-use anyhow::Result;
+use anyhow::{Result, ensure};
 use binrw::binrw;
 
-use super::{animation_flags::PPMAnimationFlags, frame::PPMFrame};
+use super::{
+    animation_flags::PPMAnimationFlags,
+    frame::PPMFrame,
+    frame_header::{PPMFrameHeader, PPMFrameType},
+};
+
+/// A frame in the timeline passed to [`PPMAnimationData::rebuild`].
+#[derive(Debug, Clone)]
+pub enum TimelineFrame {
+    /// A frame of the current animation, by index.
+    Existing(usize),
+    /// A frame that isn't part of the current animation.
+    New(PPMFrame),
+}
 
 #[binrw]
 #[brw(little)]
@@ -11,6 +24,11 @@ use super::{animation_flags::PPMAnimationFlags, frame::PPMFrame};
 #[brw(import(args: (u16, u32)))]
 #[derive(Debug, Clone, Default)]
 pub struct PPMAnimationData {
+    #[br(assert(
+        args.1 as usize >= 8 + frame_offset_table_size as usize,
+        "Animation data size {} is smaller than its header and frame offset table",
+        args.1
+    ))]
     frame_offset_table_size: u16,
     #[brw(pad_before = 4)] //unknown, always seen as 0 so we just pad instead.
     animation_flags: PPMAnimationFlags,
@@ -19,12 +37,168 @@ pub struct PPMAnimationData {
     #[br(count = args.0)]
     animation_offsets: Vec<u32>,
 
+    //animation_data_size covers the 8 byte header and the offset table as well, not just the frame data.
     #[brw(seek_before = std::io::SeekFrom::Start(0x6A8 + frame_offset_table_size.to_owned() as u64))]
-    #[br(count = args.1)]
+    #[br(count = args.1 as usize - 8 - frame_offset_table_size as usize)]
     animation_data: Vec<u8>,
 }
 
 impl PPMAnimationData {
+    /// Builds the animation section from a list of frames, recomputing the offset table and padding the frame data to 4 bytes.
+    /// Every frame after the first is diffed against its predecessor when that makes it smaller.
+    pub fn from_frames(frames: &[PPMFrame], flags: PPMAnimationFlags) -> Result<Self> {
+        let mut encoded_frames = Vec::with_capacity(frames.len());
+
+        let mut previous_frame = None;
+
+        for frame in frames.iter() {
+            encoded_frames.push(frame.encode_with_previous(previous_frame)?);
+
+            previous_frame = Some(frame);
+        }
+
+        Ok(Self::from_encoded_frames(&encoded_frames, flags))
+    }
+
+    /// Builds the animation section from already encoded frames.
+    pub fn from_encoded_frames(encoded_frames: &[Vec<u8>], flags: PPMAnimationFlags) -> Self {
+        let mut animation_offsets = Vec::with_capacity(encoded_frames.len());
+        let mut animation_data = Vec::new();
+
+        for encoded_frame in encoded_frames.iter() {
+            animation_offsets.push(animation_data.len() as u32);
+            animation_data.extend_from_slice(encoded_frame);
+        }
+
+        animation_data.resize(animation_data.len().next_multiple_of(4), 0);
+
+        Self {
+            frame_offset_table_size: (animation_offsets.len() * 4) as u16,
+            animation_flags: flags,
+            animation_offsets,
+            animation_data,
+        }
+    }
+
+    /// Splits the animation data into the encoded bytes of every frame, in playback order.
+    pub fn get_encoded_frames(&self) -> Result<Vec<Vec<u8>>> {
+        let mut encoded_frames = Vec::with_capacity(self.animation_offsets.len());
+
+        let mut cursor = Cursor::new(self.animation_data.as_slice());
+
+        for offset in self.animation_offsets.iter() {
+            cursor.seek(std::io::SeekFrom::Start(*offset as u64))?;
+
+            //frames don't store their length, parsing them is the only way to find where they end.
+            PPMFrame::parse(&mut cursor, &self.animation_flags, None)?;
+
+            let end = cursor.position() as usize;
+            encoded_frames.push(self.animation_data[*offset as usize..end].to_vec());
+        }
+
+        Ok(encoded_frames)
+    }
+
+    /// Builds a new animation section laid out as `timeline`, reusing the encoded data of existing frames where possible.
+    /// A diffed frame whose predecessor changed is converted into a self-contained frame, and a frame that directly follows a copy of itself becomes an empty diffed frame.
+    pub fn rebuild(&self, timeline: &[TimelineFrame]) -> Result<Self> {
+        let encoded_frames = self.get_encoded_frames()?;
+
+        let mut decoded_frames: Option<Vec<PPMFrame>> = None;
+
+        let mut rebuilt_frames = Vec::with_capacity(timeline.len());
+
+        for (position, timeline_frame) in timeline.iter().enumerate() {
+            let previous = match position {
+                0 => None,
+                _ => Some(&timeline[position - 1]),
+            };
+
+            let encoded_frame = match timeline_frame {
+                TimelineFrame::Existing(index) => {
+                    ensure!(
+                        *index < encoded_frames.len(),
+                        "Frame {} is out of range, the animation has {} frames",
+                        index,
+                        encoded_frames.len()
+                    );
+
+                    let encoded_frame = &encoded_frames[*index];
+
+                    let mut header = PPMFrameHeader {
+                        header: encoded_frame[0],
+                    };
+
+                    let follows_original_predecessor = *index > 0
+                        && matches!(previous, Some(TimelineFrame::Existing(i)) if *i == index - 1);
+
+                    match previous {
+                        Some(TimelineFrame::Existing(i)) if i == index => {
+                            //diffing against the same picture leaves nothing to store, only the encoding tables.
+                            header.set_frame_type(PPMFrameType::Diffed);
+                            header.set_is_translated(false);
+
+                            let mut empty_frame = vec![header.header];
+                            empty_frame.extend([0u8; 0x30 * 2]);
+                            empty_frame
+                        }
+                        _ if header.get_frame_type() == PPMFrameType::Diffed
+                            && !follows_original_predecessor =>
+                        {
+                            let decoded_frames = match decoded_frames.as_ref() {
+                                Some(decoded_frames) => decoded_frames,
+                                None => decoded_frames.insert(self.get_frames()?),
+                            };
+
+                            decoded_frames[*index].encode()?
+                        }
+                        _ => encoded_frame.clone(),
+                    }
+                }
+                TimelineFrame::New(frame) => {
+                    let previous_frame = match previous {
+                        Some(TimelineFrame::New(previous_frame)) => Some(previous_frame),
+                        Some(TimelineFrame::Existing(i)) => {
+                            let decoded_frames = match decoded_frames.as_ref() {
+                                Some(decoded_frames) => decoded_frames,
+                                None => decoded_frames.insert(self.get_frames()?),
+                            };
+
+                            decoded_frames.get(*i)
+                        }
+                        None => None,
+                    };
+
+                    frame.encode_with_previous(previous_frame)?
+                }
+            };
+
+            rebuilt_frames.push(encoded_frame);
+        }
+
+        Ok(Self::from_encoded_frames(
+            &rebuilt_frames,
+            self.animation_flags.clone(),
+        ))
+    }
+
+    /// Returns the size of the whole animation section, as stored in the file header.
+    pub fn get_size(&self) -> u32 {
+        (8 + self.frame_offset_table_size as usize + self.animation_data.len()) as u32
+    }
+
+    pub fn get_frame_count(&self) -> usize {
+        self.animation_offsets.len()
+    }
+
+    pub fn get_animation_flags(&self) -> &PPMAnimationFlags {
+        &self.animation_flags
+    }
+
+    pub fn get_animation_flags_mut(&mut self) -> &mut PPMAnimationFlags {
+        &mut self.animation_flags
+    }
+
     pub fn get_frames(&self) -> Result<Vec<PPMFrame>> {
         let mut frames = Vec::new();
 
@@ -43,3 +217,81 @@ impl PPMAnimationData {
         Ok(frames)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ppm::file::PPMFile,
+        utils::test_utils::{get_temp_dir, get_test_frame},
+    };
+
+    fn get_test_frames() -> Vec<PPMFrame> {
+        //the repeated frame diffs to nothing, the others switch between self-contained and diffed frames.
+        [0, 1, 1, 2, 4, 3].into_iter().map(get_test_frame).collect()
+    }
+
+    fn assert_same_frames(a: &[PPMFrame], b: &[PPMFrame]) {
+        assert_eq!(a.len(), b.len());
+
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert_eq!(
+                a.get_indexed_pixels().unwrap(),
+                b.get_indexed_pixels().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn from_frames_decodes_to_the_same_frames() {
+        let frames = get_test_frames();
+        let animation_data =
+            PPMAnimationData::from_frames(&frames, PPMAnimationFlags::new()).unwrap();
+
+        assert_eq!(animation_data.get_frame_count(), frames.len());
+        assert_eq!(animation_data.get_size() % 4, 0);
+        assert_same_frames(&animation_data.get_frames().unwrap(), &frames);
+    }
+
+    #[test]
+    fn set_frames_survives_save_and_reload() {
+        let mut file = PPMFile::from_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../example/flipnotes/mrjohn.ppm"
+        ))
+        .unwrap();
+
+        let frames = get_test_frames();
+        file.set_frames(&frames).unwrap();
+        file.validate().unwrap();
+
+        let path = get_temp_dir("set-frames").join("saved.ppm");
+        file.save_as(&path).unwrap();
+
+        let saved = PPMFile::from_path(&path).unwrap();
+
+        saved.validate().unwrap();
+        assert_eq!(saved.get_frame_count(), frames.len());
+        assert_eq!(
+            saved.audio.audio_header.sound_effect_flags.len(),
+            frames.len()
+        );
+        assert_same_frames(&saved.animation_data.get_frames().unwrap(), &frames);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn animation_data_size_smaller_than_the_offset_table_fails() {
+        let mut bytes = std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../example/flipnotes/mrjohn.ppm"
+        ))
+        .unwrap();
+
+        //the animation data size follows the magic.
+        bytes[4..8].copy_from_slice(&4u32.to_le_bytes());
+
+        assert!(PPMFile::from_bytes(&bytes).is_err());
+    }
+}
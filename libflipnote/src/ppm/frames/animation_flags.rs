@@ -9,6 +9,11 @@ pub struct PPMAnimationFlags {
 }
 
 impl PPMAnimationFlags {
+    /// Returns the flags used by new flipnotes, bits 0, 2 and 6 are always set in files saved by Flipnote Studio.
+    pub fn new() -> Self {
+        Self { flags: 0x45 }
+    }
+
     pub fn get_loop(&self) -> bool {
         self.flags & 0x2 != 0
     }
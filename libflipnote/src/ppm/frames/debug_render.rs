@@ -0,0 +1,546 @@
+//! Renders what the codec did with a frame, for debugging encoders and looking into how flipnotes were made.
+
+use anyhow::{Result, ensure};
+
+use crate::utils::image_utils::{ImageWrapper, RgbWrapper};
+
+use super::{frame::PPMFrame, frame_header::PPMFrameType, line::LineEncoding};
+
+const STRIP_WIDTH: usize = 6;
+
+const SKIP_COLOR: RgbWrapper = RgbWrapper {
+    r: 64,
+    g: 64,
+    b: 64,
+};
+const CODED_COLOR: RgbWrapper = RgbWrapper {
+    r: 40,
+    g: 180,
+    b: 60,
+};
+const INVERTED_CODED_COLOR: RgbWrapper = RgbWrapper {
+    r: 40,
+    g: 110,
+    b: 230,
+};
+const RAW_COLOR: RgbWrapper = RgbWrapper {
+    r: 220,
+    g: 40,
+    b: 40,
+};
+const INKED_COLOR: RgbWrapper = RgbWrapper {
+    r: 255,
+    g: 0,
+    b: 255,
+};
+const ERASED_COLOR: RgbWrapper = RgbWrapper {
+    r: 255,
+    g: 200,
+    b: 0,
+};
+const TRANSLATION_COLOR: RgbWrapper = RgbWrapper {
+    r: 0,
+    g: 220,
+    b: 220,
+};
+
+/// The heatmap goes from black through red and orange to white.
+const HEATMAP_COLORS: [RgbWrapper; 4] = [
+    RgbWrapper { r: 0, g: 0, b: 0 },
+    RgbWrapper {
+        r: 180,
+        g: 0,
+        b: 30,
+    },
+    RgbWrapper {
+        r: 255,
+        g: 140,
+        b: 0,
+    },
+    RgbWrapper {
+        r: 255,
+        g: 255,
+        b: 220,
+    },
+];
+
+/// The most bytes an 8 pixel chunk can cost: stored in both layers, each with its share of the 4 bytes of chunk flags.
+const MAX_CHUNK_SIZE: f32 = 2.0 * (1.0 + 4.0 / 32.0);
+
+/// What [`get_debug_image`] draws over the frame area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebugView {
+    /// The frame, dimmed, with the pixels diffing changed highlighted.
+    #[default]
+    Overlay,
+    /// How many bytes every 8 pixel chunk of the frame took up, both layers together.
+    Heatmap,
+}
+
+/// Controls what [`get_debug_image`] shows.
+#[derive(Debug, Clone)]
+pub struct DebugRenderOptions {
+    pub view: DebugView,
+    /// Integer scale of the whole image, so single lines and pixels stay easy to make out.
+    pub scale: u32,
+    /// Draws a strip per layer left of the frame with the encoding of every line:
+    /// dark gray for skipped, green for coded, blue for inverted coded and red for raw lines.
+    pub line_encodings: bool,
+    /// Marks pixels diffing inked in magenta and pixels it erased in amber. Only has an effect on [`DebugView::Overlay`].
+    pub diffing: bool,
+    /// Draws the translation of diffed frames as a cyan line from the center of the frame.
+    pub translation: bool,
+}
+
+impl Default for DebugRenderOptions {
+    fn default() -> Self {
+        Self {
+            view: DebugView::default(),
+            scale: 2,
+            line_encodings: true,
+            diffing: true,
+            translation: true,
+        }
+    }
+}
+
+impl DebugRenderOptions {
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            (1..=32).contains(&self.scale),
+            "Debug scale must be between 1 and 32"
+        );
+
+        Ok(())
+    }
+
+    /// The width of the image, with the margin for the line encodings.
+    pub fn get_width(&self) -> u32 {
+        (256 + self.get_margin() as u32) * self.scale
+    }
+
+    pub fn get_height(&self) -> u32 {
+        192 * self.scale
+    }
+
+    fn get_margin(&self) -> usize {
+        match self.line_encodings {
+            true => 2 * (STRIP_WIDTH + 1),
+            false => 0,
+        }
+    }
+}
+
+/// How a frame is stored in the file, recovered from the decoded frame and the one before it.
+#[derive(Debug, Clone)]
+pub struct FrameEncodingInfo {
+    pub frame_type: PPMFrameType,
+    /// `None` unless the frame is translated.
+    pub translation: Option<(i8, i8)>,
+    /// The encoding of every line of layer 1 and layer 2, as read from the file.
+    pub encodings: [Vec<LineEncoding>; 2],
+    /// The pixels of both layers as stored, 1 byte per pixel row by row. For diffed frames these are the pixels that flip compared to the previous frame.
+    pub stored: [Vec<u8>; 2],
+}
+
+impl FrameEncodingInfo {
+    /// `previous` is the decoded frame before `frame`, needed to undo the diffing of diffed frames.
+    pub fn new(frame: &PPMFrame, previous: Option<&PPMFrame>) -> Result<Self> {
+        let header = frame.get_header();
+        let frame_type = header.get_frame_type();
+
+        let translation = match header.get_is_translated() {
+            true => Some(frame.get_translation()),
+            false => None,
+        };
+
+        let (translate_x, translate_y) = frame.get_translation();
+        let (translate_x, translate_y) = (translate_x as isize, translate_y as isize);
+
+        let mut encodings = [Vec::with_capacity(192), Vec::with_capacity(192)];
+        let mut stored = [Vec::with_capacity(256 * 192), Vec::with_capacity(256 * 192)];
+
+        for layer in 0..2 {
+            let lines = &frame.get_layer(layer as u8 + 1)?.lines;
+
+            for (y, line) in lines.iter().enumerate() {
+                encodings[layer].push(line.encoding);
+
+                let mut data = line.get_data();
+
+                //the same offsets decode_diffing reads the previous frame from.
+                let previous_y = y as isize - translate_y;
+
+                if let Some(previous) = previous.filter(|_| {
+                    frame_type == PPMFrameType::Diffed && (0..192).contains(&previous_y)
+                }) {
+                    let previous_data =
+                        previous.get_layer(layer as u8 + 1)?.lines[previous_y as usize].get_data();
+
+                    for (x, pixel) in data.iter_mut().enumerate() {
+                        let previous_x = x as isize - translate_x;
+
+                        if (0..256).contains(&previous_x) {
+                            *pixel ^= previous_data[previous_x as usize];
+                        }
+                    }
+                }
+
+                stored[layer].extend_from_slice(&data);
+            }
+        }
+
+        Ok(Self {
+            frame_type,
+            translation,
+            encodings,
+            stored,
+        })
+    }
+
+    /// Packs a line of the stored pixels into chunks of 8, like [`PPMLine::get_chunks`](super::line::PPMLine::get_chunks).
+    fn get_stored_chunks(&self, layer: usize, y: usize) -> [u8; 32] {
+        let mut chunks = [0u8; 32];
+
+        for (i, pixel) in self.stored[layer][y * 256..(y + 1) * 256]
+            .iter()
+            .enumerate()
+        {
+            chunks[i / 8] |= (pixel & 0x1) << (i % 8);
+        }
+
+        chunks
+    }
+
+    /// The bytes a line of a layer takes up in the file. `layer` is 0 for layer 1 and 1 for layer 2.
+    pub fn get_line_size(&self, layer: usize, y: usize) -> usize {
+        let chunks = self.get_stored_chunks(layer, y);
+
+        match self.encodings[layer][y] {
+            LineEncoding::Skip => 0,
+            LineEncoding::Coded => 4 + chunks.iter().filter(|chunk| **chunk != 0x00).count(),
+            LineEncoding::InvertedCoded => {
+                4 + chunks.iter().filter(|chunk| **chunk != 0xFF).count()
+            }
+            LineEncoding::Raw => 32,
+        }
+    }
+
+    /// The bytes every 8 pixel chunk takes up across both layers, 32 per line. The chunk flags of coded lines are shared evenly between their chunks.
+    pub fn get_chunk_sizes(&self) -> Vec<f32> {
+        let mut sizes = vec![0f32; 32 * 192];
+
+        for layer in 0..2 {
+            for y in 0..192 {
+                let chunks = self.get_stored_chunks(layer, y);
+
+                for (x, chunk) in chunks.iter().enumerate() {
+                    sizes[y * 32 + x] += match self.encodings[layer][y] {
+                        LineEncoding::Skip => 0.0,
+                        LineEncoding::Coded => (*chunk != 0x00) as u8 as f32 + 4.0 / 32.0,
+                        LineEncoding::InvertedCoded => (*chunk != 0xFF) as u8 as f32 + 4.0 / 32.0,
+                        LineEncoding::Raw => 1.0,
+                    };
+                }
+            }
+        }
+
+        sizes
+    }
+
+    /// The size of the whole frame in the file: the header, the translation, both encoding tables and the line data.
+    pub fn get_size(&self) -> usize {
+        let translation_size = match self.translation {
+            Some(_) => 2,
+            None => 0,
+        };
+
+        let lines_size = (0..2)
+            .flat_map(|layer| (0..192).map(move |y| (layer, y)))
+            .map(|(layer, y)| self.get_line_size(layer, y))
+            .sum::<usize>();
+
+        1 + translation_size + 2 * 0x30 + lines_size
+    }
+}
+
+/// Renders the encoding of a frame in the [`DebugView`] of the options. `previous` is the decoded frame before it, diffed frames can't be taken apart without it.
+/// The image is [`DebugRenderOptions::get_width`] by [`DebugRenderOptions::get_height`] pixels, the line encodings to the left of the frame.
+pub fn get_debug_image(
+    frame: &PPMFrame,
+    previous: Option<&PPMFrame>,
+    options: &DebugRenderOptions,
+) -> Result<ImageWrapper> {
+    options.validate()?;
+
+    let info = FrameEncodingInfo::new(frame, previous)?;
+
+    let margin = options.get_margin();
+    let width = 256 + margin;
+
+    //drawn at 1x, then scaled.
+    let mut pixels = vec![RgbWrapper::new(0, 0, 0); width * 192];
+
+    if options.line_encodings {
+        for (layer, encodings) in info.encodings.iter().enumerate() {
+            let start = layer * (STRIP_WIDTH + 1);
+
+            for (y, encoding) in encodings.iter().enumerate() {
+                let color = match encoding {
+                    LineEncoding::Skip => SKIP_COLOR,
+                    LineEncoding::Coded => CODED_COLOR,
+                    LineEncoding::InvertedCoded => INVERTED_CODED_COLOR,
+                    LineEncoding::Raw => RAW_COLOR,
+                };
+
+                pixels[y * width + start..y * width + start + STRIP_WIDTH].fill(color);
+            }
+        }
+    }
+
+    match options.view {
+        DebugView::Overlay => {
+            let colors = frame.get_palette()?;
+            let indices = frame.get_indexed_pixels()?;
+            let diffed = options.diffing && info.frame_type == PPMFrameType::Diffed;
+
+            for (i, index) in indices.iter().enumerate() {
+                let changed = info.stored[0][i] | info.stored[1][i] != 0;
+
+                pixels[(i / 256) * width + margin + i % 256] = match (diffed && changed, index) {
+                    (true, 0) => ERASED_COLOR,
+                    (true, _) => INKED_COLOR,
+                    //dimmed towards gray so the changes stand out.
+                    (false, _) if diffed => mix(&colors[*index as usize], 0.35),
+                    (false, _) => colors[*index as usize],
+                };
+            }
+        }
+        DebugView::Heatmap => {
+            for (i, size) in info.get_chunk_sizes().iter().enumerate() {
+                let start = (i / 32) * width + margin + (i % 32) * 8;
+
+                pixels[start..start + 8].fill(get_heatmap_color(size / MAX_CHUNK_SIZE));
+            }
+        }
+    }
+
+    if let Some((translate_x, translate_y)) = info.translation.filter(|_| options.translation) {
+        let (x, y) = (128 + translate_x as isize, 96 + translate_y as isize);
+
+        for (x, y) in get_line_points((128, 96), (x, y)) {
+            pixels[y as usize * width + margin + x as usize] = TRANSLATION_COLOR;
+        }
+
+        //marks where the line ends, even for a translation of 0.
+        for (dx, dy) in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy))) {
+            let (x, y) = (x + dx, y + dy);
+
+            if (0..256).contains(&x) && (0..192).contains(&y) {
+                pixels[y as usize * width + margin + x as usize] = TRANSLATION_COLOR;
+            }
+        }
+    }
+
+    let scale = options.scale as usize;
+    let mut raw_pixels = Vec::with_capacity(pixels.len() * scale * scale * 4);
+
+    for row in pixels.chunks(width) {
+        let row = row
+            .iter()
+            .flat_map(|color| std::iter::repeat_n([color.r, color.g, color.b, 255], scale))
+            .flatten()
+            .collect::<Vec<u8>>();
+
+        for _ in 0..scale {
+            raw_pixels.extend_from_slice(&row);
+        }
+    }
+
+    ImageWrapper::from_raw_pixels(options.get_width(), options.get_height(), raw_pixels)
+}
+
+/// Pulls a color towards mid gray, keeping `contrast` of it.
+fn mix(color: &RgbWrapper, contrast: f32) -> RgbWrapper {
+    let channel = |value: u8| (128.0 + (value as f32 - 128.0) * contrast).round() as u8;
+
+    RgbWrapper::new(channel(color.r), channel(color.g), channel(color.b))
+}
+
+/// Maps 0 to 1 onto [`HEATMAP_COLORS`].
+fn get_heatmap_color(value: f32) -> RgbWrapper {
+    let position = value.clamp(0.0, 1.0) * (HEATMAP_COLORS.len() - 1) as f32;
+    let index = (position.floor() as usize).min(HEATMAP_COLORS.len() - 2);
+    let t = position - index as f32;
+
+    let (from, to) = (&HEATMAP_COLORS[index], &HEATMAP_COLORS[index + 1]);
+    let channel = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * t).round() as u8;
+
+    RgbWrapper::new(
+        channel(from.r, to.r),
+        channel(from.g, to.g),
+        channel(from.b, to.b),
+    )
+}
+
+/// Returns the points of a line from `start` to `end` that fall inside the frame.
+fn get_line_points(start: (isize, isize), end: (isize, isize)) -> Vec<(isize, isize)> {
+    let (dx, dy) = ((end.0 - start.0).abs(), -(end.1 - start.1).abs());
+    let (step_x, step_y) = ((end.0 - start.0).signum(), (end.1 - start.1).signum());
+
+    let (mut x, mut y) = start;
+    let mut error = dx + dy;
+    let mut points = Vec::new();
+
+    loop {
+        if (0..256).contains(&x) && (0..192).contains(&y) {
+            points.push((x, y));
+        }
+
+        if (x, y) == end {
+            break;
+        }
+
+        let doubled = 2 * error;
+
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::ppm::frames::{animation_flags::PPMAnimationFlags, frame_header::PPMPaperColor};
+
+    /// A frame on white paper with one empty, one sparse, one full and one noisy row on layer 1, so each row gets a different encoding.
+    fn get_frame() -> PPMFrame {
+        let mut frame = PPMFrame::default();
+        frame.get_header_mut().set_paper_color(PPMPaperColor::White);
+
+        let layer = frame.get_layer_mut(1).unwrap();
+        layer.set(9, 1, true).unwrap();
+
+        for x in 0..256 {
+            layer.set(x, 2, true).unwrap();
+            layer.set(x, 3, x % 2 == 0).unwrap();
+        }
+
+        frame
+    }
+
+    fn decode(bytes: &[u8], previous_frame: Option<PPMFrame>) -> PPMFrame {
+        PPMFrame::parse(
+            &mut Cursor::new(bytes),
+            &PPMAnimationFlags::new(),
+            previous_frame,
+        )
+        .unwrap()
+    }
+
+    fn get_pixel(image: &ImageWrapper, x: usize, y: usize) -> [u8; 3] {
+        let pixels = image.get_raw_pixels();
+        let i = (y * image.get_width() as usize + x) * 4;
+
+        [pixels[i], pixels[i + 1], pixels[i + 2]]
+    }
+
+    fn get_rgb(color: RgbWrapper) -> [u8; 3] {
+        [color.r, color.g, color.b]
+    }
+
+    #[test]
+    fn line_encodings_and_sizes_match_the_file() {
+        let encoded = get_frame().encode().unwrap();
+        let frame = decode(&encoded, None);
+
+        let info = FrameEncodingInfo::new(&frame, None).unwrap();
+
+        assert_eq!(
+            info.encodings[0][..5],
+            [
+                LineEncoding::Skip,
+                LineEncoding::Coded,
+                LineEncoding::InvertedCoded,
+                LineEncoding::Raw,
+                LineEncoding::Skip
+            ]
+        );
+        assert_eq!(info.get_size(), encoded.len());
+
+        let options = DebugRenderOptions {
+            view: DebugView::Heatmap,
+            scale: 1,
+            ..Default::default()
+        };
+
+        let image = get_debug_image(&frame, None, &options).unwrap();
+        let margin = options.get_margin();
+
+        assert_eq!((image.get_width(), image.get_height()), (256 + 14, 192));
+
+        //one strip per layer, with a gap between them.
+        for (y, color) in [SKIP_COLOR, CODED_COLOR, INVERTED_CODED_COLOR, RAW_COLOR]
+            .into_iter()
+            .enumerate()
+        {
+            assert_eq!(get_pixel(&image, 0, y), get_rgb(color));
+            assert_eq!(get_pixel(&image, STRIP_WIDTH, y), [0, 0, 0]);
+            assert_eq!(get_pixel(&image, STRIP_WIDTH + 1, y), get_rgb(SKIP_COLOR));
+        }
+
+        //skipped lines cost nothing, every chunk of a coded line carries its share of the flags, the chunk with ink a byte on top.
+        assert_eq!(get_pixel(&image, margin, 0), [0, 0, 0]);
+        assert_eq!(get_pixel(&image, margin, 1), [30, 0, 5]);
+        assert_eq!(get_pixel(&image, margin + 8, 1), [218, 70, 15]);
+    }
+
+    #[test]
+    fn overlay_marks_what_diffing_changed() {
+        let previous = get_frame();
+
+        let mut frame = previous.clone();
+        let layer = frame.get_layer_mut(1).unwrap();
+        layer.set(9, 1, false).unwrap();
+        layer.set(20, 10, true).unwrap();
+
+        let frame = decode(
+            &frame.encode_diffed(&previous, 0, 0).unwrap(),
+            Some(previous.clone()),
+        );
+
+        let options = DebugRenderOptions {
+            scale: 1,
+            line_encodings: false,
+            ..Default::default()
+        };
+
+        let image = get_debug_image(&frame, Some(&previous), &options).unwrap();
+
+        assert_eq!(image.get_width(), 256);
+        assert_eq!(get_pixel(&image, 20, 10), get_rgb(INKED_COLOR));
+        assert_eq!(get_pixel(&image, 9, 1), get_rgb(ERASED_COLOR));
+        //everything else is dimmed, white paper turns light gray.
+        assert_eq!(get_pixel(&image, 0, 0), [172, 172, 172]);
+
+        let options = DebugRenderOptions {
+            diffing: false,
+            ..options
+        };
+
+        let image = get_debug_image(&frame, Some(&previous), &options).unwrap();
+
+        assert_eq!(get_pixel(&image, 9, 1), [255, 255, 255]);
+        assert_eq!(get_pixel(&image, 0, 0), [255, 255, 255]);
+    }
+}
@@ -1,17 +1,46 @@
-use std::io::{Cursor, Read};
+use std::io::{Cursor, Read, Write};
 
-use anyhow::Result;
-use binrw::BinReaderExt;
+use anyhow::{Result, ensure};
+use binrw::{BinReaderExt, BinWrite, BinWriterExt};
 
-use crate::utils::image_utils::ImageWrapper;
+use crate::{
+    ppm::constants::{PPM_COLOR_BLUE, PPM_COLOR_RED, PPM_PAPER_COLORS, PPM_TRANSLATE_SEARCH_RANGE},
+    utils::{
+        color_utils::rgb_to_ppm_frame_pixel,
+        image_utils::{DitherType, ImageWrapper, PPMFrameColorMap, ResizeMode, RgbWrapper},
+        upscale_utils::upscale_indexed,
+    },
+};
 
 use super::{
     animation_flags::PPMAnimationFlags,
-    frame_header::{PPMFrameHeader, PPMFrameType},
+    frame_header::{PPMFrameHeader, PPMFrameType, PPMLayerColor, PPMPaperColor},
     layer::PPMLayer,
     line::LineEncoding,
+    render_options::{PaperMode, RenderOptions},
+    render_sink::{IndexSink, RenderInfo, RenderRow, RenderSink, RgbaSink},
 };
 
+/// Controls how [`PPMFrame::from_image`] turns an image into a frame.
+#[derive(Debug, Clone)]
+pub struct FrameImportOptions {
+    pub resize_mode: ResizeMode,
+    /// `None` maps every pixel to its nearest color without dithering.
+    pub dither_type: Option<DitherType>,
+    /// Fills transparent pixels, and the borders left by [`ResizeMode::Fit`].
+    pub background_color: RgbWrapper,
+}
+
+impl Default for FrameImportOptions {
+    fn default() -> Self {
+        Self {
+            resize_mode: ResizeMode::default(),
+            dither_type: Some(DitherType::default()),
+            background_color: PPM_PAPER_COLORS[0],
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct PPMFrame {
     header: PPMFrameHeader,
@@ -71,29 +100,315 @@ impl PPMFrame {
         Ok(frame)
     }
 
+    /// Converts any image into a 256x192 frame.
+    /// The more common of white and black becomes the paper, and the two most common of the remaining colors (the inverse of the paper, red and blue) become the layers.
+    /// A frame can only show 3 colors, so pixels of the unused ink color are mapped to the nearest color that is available.
+    pub fn from_image(image: &ImageWrapper, options: FrameImportOptions) -> Result<Self> {
+        let image =
+            image.resize_with_mode(256, 192, options.resize_mode, &options.background_color)?;
+
+        let image = match options.dither_type {
+            Some(dither_type) => image.dither(dither_type, PPMFrameColorMap)?,
+            None => image,
+        };
+
+        //indexes are white, black, red and blue, in the order of PPMFrameColorMap.
+        let colors = [
+            PPM_PAPER_COLORS[0],
+            PPM_PAPER_COLORS[1],
+            PPM_COLOR_RED,
+            PPM_COLOR_BLUE,
+        ];
+
+        let pixels = image
+            .get_pixels()?
+            .iter()
+            .map(rgb_to_ppm_frame_pixel)
+            .collect::<Vec<usize>>();
+
+        let mut color_counts = [0usize; 4];
+
+        for pixel in pixels.iter() {
+            color_counts[*pixel] += 1;
+        }
+
+        let paper = match color_counts[1] > color_counts[0] {
+            true => 1,
+            false => 0,
+        };
+
+        let mut inks = [1 - paper, 2, 3];
+        inks.sort_by_key(|ink| std::cmp::Reverse(color_counts[*ink]));
+
+        let available = [paper, inks[0], inks[1]];
+
+        let remap = std::array::from_fn::<usize, 4, _>(|index| {
+            *available
+                .iter()
+                .min_by(|a, b| {
+                    colors[index]
+                        .distance(&colors[**a])
+                        .total_cmp(&colors[index].distance(&colors[**b]))
+                })
+                .unwrap()
+        });
+
+        let mut frame = Self::default();
+
+        frame.header.set_frame_type(PPMFrameType::Normal);
+        frame.header.set_paper_color(PPMPaperColor::from(paper));
+
+        for (layer, ink) in inks.iter().take(2).enumerate() {
+            let layer_color = match ink {
+                2 => PPMLayerColor::Red,
+                3 => PPMLayerColor::Blue,
+                _ => PPMLayerColor::InverseOfPaper,
+            };
+
+            frame.header.set_layer_color(layer as u8 + 1, layer_color)?;
+        }
+
+        for (i, pixel) in pixels.iter().enumerate() {
+            let index = remap[*pixel];
+
+            //layer 1 is drawn on top, but the layers never overlap here.
+            if let Some(layer) = inks.iter().take(2).position(|ink| *ink == index) {
+                frame.layers[layer].set(i % 256, i / 256, true)?;
+            }
+        }
+
+        Ok(frame)
+    }
+
+    /// Encodes the frame as a self-contained (non-diffed) frame, picking the smallest encoding for every line.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut header = self.header.clone();
+        header.set_frame_type(PPMFrameType::Normal);
+        header.set_is_translated(false);
+
+        let mut buffer = Vec::new();
+        let mut cursor = Cursor::new(&mut buffer);
+
+        Self::write_frame(&mut cursor, &header, (0, 0), &self.layers)?;
+
+        Ok(buffer)
+    }
+
+    /// Encodes the frame as a diffed frame, XORed against the previous frame moved by the given translation.
+    /// Mirrors [`PPMFrame::decode_diffing`], so decoding the result on top of `previous_frame` gives back this frame.
+    pub fn encode_diffed(
+        &self,
+        previous_frame: &PPMFrame,
+        translate_x: i8,
+        translate_y: i8,
+    ) -> Result<Vec<u8>> {
+        let mut header = self.header.clone();
+        header.set_frame_type(PPMFrameType::Diffed);
+        header.set_is_translated(translate_x != 0 || translate_y != 0);
+
+        let mut layers = self.layers.clone();
+
+        for (layer, previous_layer) in layers.iter_mut().zip(previous_frame.layers.iter()) {
+            for y in 0..192isize {
+                let source_y = y - translate_y as isize;
+
+                if !(0..192).contains(&source_y) {
+                    continue;
+                }
+
+                for x in 0..256isize {
+                    let source_x = x - translate_x as isize;
+
+                    if !(0..256).contains(&source_x) {
+                        continue;
+                    }
+
+                    let previous_value =
+                        previous_layer.get(source_x as usize, source_y as usize)?;
+
+                    layer.apply_diffing(x as usize, y as usize, previous_value as u8)?;
+                }
+            }
+        }
+
+        let mut buffer = Vec::new();
+        let mut cursor = Cursor::new(&mut buffer);
+
+        Self::write_frame(&mut cursor, &header, (translate_x, translate_y), &layers)?;
+
+        Ok(buffer)
+    }
+
+    /// Encodes the frame in whichever way is smallest: as a self-contained frame, or diffed against the previous frame
+    /// using the translation found by [`PPMFrame::find_best_translation`].
+    pub fn encode_with_previous(&self, previous_frame: Option<&PPMFrame>) -> Result<Vec<u8>> {
+        let Some(previous_frame) = previous_frame else {
+            return self.encode();
+        };
+
+        let current_rows = self.get_packed_rows();
+        let empty_rows = [vec![[0u64; 4]; 192], vec![[0u64; 4]; 192]];
+
+        let normal_size = estimate_encoded_size(&current_rows, &empty_rows, (0, 0), usize::MAX);
+
+        let (translate_x, translate_y) = self.find_best_translation(previous_frame);
+
+        let diffed_size = estimate_encoded_size(
+            &current_rows,
+            &previous_frame.get_packed_rows(),
+            (translate_x, translate_y),
+            normal_size,
+        );
+
+        if diffed_size < normal_size {
+            self.encode_diffed(previous_frame, translate_x, translate_y)
+        } else {
+            self.encode()
+        }
+    }
+
+    /// Searches for the translation of the previous frame that makes the diffed frame the smallest.
+    /// Candidates are tried from the center outwards, within [`PPM_TRANSLATE_SEARCH_RANGE`] pixels in both directions.
+    pub fn find_best_translation(&self, previous_frame: &PPMFrame) -> (i8, i8) {
+        let current_rows = self.get_packed_rows();
+        let previous_rows = previous_frame.get_packed_rows();
+
+        let mut best_translation = (0, 0);
+        let mut best_size =
+            estimate_encoded_size(&current_rows, &previous_rows, (0, 0), usize::MAX);
+
+        //a frame that diffs down to nothing but the encoding tables can't get any smaller.
+        let minimum_size = 1 + 0x30 * 2;
+
+        for distance in 1..=PPM_TRANSLATE_SEARCH_RANGE {
+            if best_size <= minimum_size {
+                break;
+            }
+
+            for translate_y in -distance..=distance {
+                for translate_x in -distance..=distance {
+                    if translate_x.abs() != distance && translate_y.abs() != distance {
+                        continue;
+                    }
+
+                    let size = estimate_encoded_size(
+                        &current_rows,
+                        &previous_rows,
+                        (translate_x, translate_y),
+                        best_size,
+                    );
+
+                    if size < best_size {
+                        best_size = size;
+                        best_translation = (translate_x, translate_y);
+                    }
+                }
+            }
+        }
+
+        best_translation
+    }
+
+    fn get_packed_rows(&self) -> [Vec<[u64; 4]>; 2] {
+        self.layers.clone().map(|layer| {
+            layer
+                .lines
+                .iter()
+                .map(|line| {
+                    let chunks = line.get_chunks();
+
+                    std::array::from_fn(|i| {
+                        u64::from_le_bytes(chunks[i * 8..i * 8 + 8].try_into().unwrap())
+                    })
+                })
+                .collect()
+        })
+    }
+
+    fn write_frame(
+        cursor: &mut Cursor<&mut Vec<u8>>,
+        header: &PPMFrameHeader,
+        translate: (i8, i8),
+        layers: &[PPMLayer; 2],
+    ) -> Result<()> {
+        header.write(cursor)?;
+
+        if header.get_is_translated() {
+            cursor.write_le(&translate.0)?;
+            cursor.write_le(&translate.1)?;
+        }
+
+        for layer in layers.iter() {
+            cursor.write_all(&layer.get_packed_encodings())?;
+        }
+
+        for layer in layers.iter() {
+            layer.encode(cursor)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_header(&self) -> &PPMFrameHeader {
+        &self.header
+    }
+
+    pub fn get_header_mut(&mut self) -> &mut PPMFrameHeader {
+        &mut self.header
+    }
+
+    /// How far the previous frame was moved before diffing against it, as (x, y). Only used when the header says the frame is translated.
+    pub fn get_translation(&self) -> (i8, i8) {
+        (self.translate_x, self.translate_y)
+    }
+
+    pub fn get_layer(&self, layer: u8) -> Result<&PPMLayer> {
+        ensure!(layer > 0 && layer <= 2, "Layer index must be 1 or 2");
+
+        Ok(&self.layers[layer as usize - 1])
+    }
+
+    pub fn get_layer_mut(&mut self, layer: u8) -> Result<&mut PPMLayer> {
+        ensure!(layer > 0 && layer <= 2, "Layer index must be 1 or 2");
+
+        Ok(&mut self.layers[layer as usize - 1])
+    }
+
     pub fn decode_diffing(&mut self, previous_frame: &PPMFrame) -> Result<()> {
         if self.header.get_frame_type() != PPMFrameType::Diffed {
             return Ok(());
         }
 
-        let translate_y = self.translate_y as usize;
-        let translate_x = self.translate_x as usize;
+        let translate_y = self.translate_y as isize;
+        let translate_x = self.translate_x as isize;
+
+        for y in 0..192isize {
+            if y - translate_y < 0 {
+                continue;
+            }
 
-        for y in 0..192 {
             if y - translate_y >= 192 {
                 break;
             }
 
-            for x in 0..256 {
+            for x in 0..256isize {
+                if x - translate_x < 0 {
+                    continue;
+                }
+
                 if x - translate_x >= 256 {
                     break;
                 }
 
                 for layer in 0..2 {
-                    let previous_value =
-                        previous_frame.layers[layer].get(x - translate_x, y - translate_y)?;
+                    let previous_value = previous_frame.layers[layer]
+                        .get((x - translate_x) as usize, (y - translate_y) as usize)?;
 
-                    self.layers[layer].apply_diffing(x, y, previous_value as u8)?;
+                    self.layers[layer].apply_diffing(
+                        x as usize,
+                        y as usize,
+                        previous_value as u8,
+                    )?;
                 }
             }
         }
@@ -101,32 +416,392 @@ impl PPMFrame {
         Ok(())
     }
 
-    pub fn get_image(&self) -> Result<ImageWrapper> {
-        let mut image = ImageWrapper::new(256, 192);
+    /// Returns the paper color followed by the colors of layer 1 and 2, indexed by [`PPMFrame::get_indexed_pixels`].
+    pub fn get_palette(&self) -> Result<[RgbWrapper; 3]> {
+        self.get_palette_with(&RenderOptions::default())
+    }
 
+    /// Like [`PPMFrame::get_palette`], in the palette of the options and through the color response of their LCD emulation.
+    /// The paper is replaced if the options choose a color for it. With transparent paper the frame's paper color is kept, use [`RenderOptions::get_transparent_paper`] to tell.
+    pub fn get_palette_with(&self, options: &RenderOptions) -> Result<[RgbWrapper; 3]> {
+        let palette = &options.palette;
         let paper_color = self.header.get_paper_color();
 
-        let layer_1_color = self.header.get_layer_color(1)?.get_rgb_color(&paper_color);
-        let layer_2_color = self.header.get_layer_color(2)?.get_rgb_color(&paper_color);
-        let paper_color = paper_color.get_rgb_color();
+        let paper = match options.paper {
+            PaperMode::Color(color) => color,
+            PaperMode::Frame | PaperMode::Transparent => palette.get_paper_color(&paper_color),
+        };
+
+        let colors = [
+            paper,
+            palette.get_layer_color(&self.header.get_layer_color(1)?, &paper_color),
+            palette.get_layer_color(&self.header.get_layer_color(2)?, &paper_color),
+        ];
+
+        Ok(match &options.lcd_emulation {
+            Some(lcd_emulation) => colors.map(|color| lcd_emulation.apply_color(color)),
+            None => colors,
+        })
+    }
+
+    /// Returns one palette index per pixel, row by row: 0 for paper, 1 for layer 1 and 2 for layer 2. Hidden layers are skipped.
+    pub fn get_indexed_pixels(&self) -> Result<Vec<u8>> {
+        self.get_indexed_pixels_with(&RenderOptions::default())
+    }
+
+    /// Like [`PPMFrame::get_indexed_pixels`], at the size, upscaler and layer visibility of the options.
+    pub fn get_indexed_pixels_with(&self, options: &RenderOptions) -> Result<Vec<u8>> {
+        let mut sink = IndexSink::new();
+
+        self.render_to(&mut sink, options)?;
+
+        Ok(sink.into_pixels())
+    }
+
+    pub fn get_image(&self) -> Result<ImageWrapper> {
+        self.get_image_with(&RenderOptions::default())
+    }
+
+    /// Renders the frame as an RGBA image of [`RenderOptions::get_width`] by [`RenderOptions::get_height`] pixels.
+    /// Transparent paper keeps its color with an alpha of 0.
+    pub fn get_image_with(&self, options: &RenderOptions) -> Result<ImageWrapper> {
+        let mut sink = RgbaSink::new();
+
+        self.render_to(&mut sink, options)?;
+
+        ImageWrapper::from_raw_pixels(
+            options.get_width(),
+            options.get_height(),
+            sink.into_pixels(),
+        )
+    }
+
+    /// Renders the frame with `options` into `sink`, one row at a time from the top.
+    pub fn render_to(&self, sink: &mut impl RenderSink, options: &RenderOptions) -> Result<()> {
+        options.validate()?;
+
+        let show_layers = [
+            options.layer_1_visible.unwrap_or(!self.hide_layer_1),
+            options.layer_2_visible.unwrap_or(!self.hide_layer_2),
+        ];
+
+        let mut pixels = vec![0u8; 256 * 192];
+        let mut layer_ink = [vec![], vec![]];
 
         for y in 0..192 {
             for x in 0..256 {
-                image.set_pixel(x, y, &paper_color)?;
-                let layer_1 = self.layers[0].get(x as usize, y as usize)?;
-                let layer_2 = self.layers[1].get(x as usize, y as usize)?;
+                //top layer is stored first.
+                if show_layers[0] && self.layers[0].get(x, y)? {
+                    pixels[y * 256 + x] = 1;
+                } else if show_layers[1] && self.layers[1].get(x, y)? {
+                    pixels[y * 256 + x] = 2;
+                }
+            }
+        }
+
+        if sink.needs_layer_ink() {
+            for (layer, ink) in layer_ink.iter_mut().enumerate() {
+                *ink = vec![0u8; 256 * 192];
 
-                if !self.hide_layer_2 && layer_2 {
-                    image.set_pixel(x, y, &layer_2_color)?;
+                if !show_layers[layer] {
+                    continue;
                 }
 
-                //top layer is stored first.
-                if !self.hide_layer_1 && layer_1 {
-                    image.set_pixel(x, y, &layer_1_color)?;
+                for y in 0..192 {
+                    for x in 0..256 {
+                        ink[y * 256 + x] = self.layers[layer].get(x, y)? as u8;
+                    }
+                }
+            }
+        }
+
+        let colors = self.get_palette_with(options)?;
+        let transparent_paper = options.get_transparent_paper();
+
+        if options.scale > 1 {
+            //upscalers compare what is drawn, so two layers in the same color are smoothed as one.
+            let is_transparent = |index: usize| index == 0 && transparent_paper;
+
+            let classes = (0..3)
+                .map(|index| {
+                    (0..=index)
+                        .find(|other| {
+                            colors[*other] == colors[index]
+                                && is_transparent(*other) == is_transparent(index)
+                        })
+                        .unwrap_or(index) as u8
+                })
+                .collect::<Vec<u8>>();
+
+            let upscale = |pixels: &[u8], classes: &[u8]| {
+                upscale_indexed(pixels, 256, 192, options.scale, options.upscaler, classes)
+            };
+
+            pixels = upscale(&pixels, &classes);
+
+            for ink in layer_ink.iter_mut().filter(|ink| !ink.is_empty()) {
+                *ink = upscale(ink, &[0, 1]);
+            }
+        }
+
+        let width = options.get_width() as usize;
+
+        sink.begin(&RenderInfo {
+            width: options.get_width(),
+            height: options.get_height(),
+            scale: options.scale,
+            colors,
+            transparent_paper,
+            lcd_emulation: options.lcd_emulation,
+        })?;
+
+        for (y, indices) in pixels.chunks_exact(width).enumerate() {
+            //empty if the sink didn't ask for the ink.
+            let layer_ink = layer_ink
+                .each_ref()
+                .map(|ink| ink.get(y * width..(y + 1) * width).unwrap_or(&[]));
+
+            sink.write_row(&RenderRow {
+                y: y as u32,
+                indices,
+                layer_ink,
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns 64 pixels of a packed row starting at `start`, pixels outside the row read as 0.
+fn read_packed_bits(row: &[u64; 4], start: isize) -> u64 {
+    let word = start.div_euclid(64);
+    let offset = start.rem_euclid(64);
+
+    let get_word = |index: isize| match index {
+        0..4 => row[index as usize],
+        _ => 0,
+    };
+
+    match offset {
+        0 => get_word(word),
+        _ => (get_word(word) >> offset) | (get_word(word + 1) << (64 - offset)),
+    }
+}
+
+/// Estimates the size of a frame diffed against `previous_rows` moved by `translate`, using the same line encoding rules as [`super::line::PPMLine::get_best_encoding`].
+/// Stops counting once the size exceeds `limit`, since the caller is only interested in smaller candidates.
+fn estimate_encoded_size(
+    current_rows: &[Vec<[u64; 4]>; 2],
+    previous_rows: &[Vec<[u64; 4]>; 2],
+    translate: (i8, i8),
+    limit: usize,
+) -> usize {
+    let (translate_x, translate_y) = (translate.0 as isize, translate.1 as isize);
+
+    let mut size = 1 + 0x30 * 2;
+
+    if translate_x != 0 || translate_y != 0 {
+        size += 2;
+    }
+
+    for (current_layer, previous_layer) in current_rows.iter().zip(previous_rows.iter()) {
+        for (y, current_row) in current_layer.iter().enumerate() {
+            let source_y = y as isize - translate_y;
+
+            let mut row = *current_row;
+
+            if (0..192).contains(&source_y) {
+                let previous_row = &previous_layer[source_y as usize];
+
+                for (i, word) in row.iter_mut().enumerate() {
+                    *word ^= read_packed_bits(previous_row, i as isize * 64 - translate_x);
+                }
+            }
+
+            let chunks = row.iter().flat_map(|word| word.to_le_bytes());
+
+            let coded_size = chunks.clone().filter(|c| *c != 0x00).count();
+            let inverted_size = chunks.filter(|c| *c != 0xFF).count();
+
+            if coded_size != 0 {
+                size += (coded_size.min(inverted_size) + 4).min(32);
+            }
+
+            if size > limit {
+                return size;
+            }
+        }
+    }
+
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::get_test_frame;
+
+    fn decode(bytes: &[u8], previous_frame: Option<PPMFrame>) -> PPMFrame {
+        let mut cursor = Cursor::new(bytes);
+        let frame =
+            PPMFrame::parse(&mut cursor, &PPMAnimationFlags::new(), previous_frame).unwrap();
+
+        assert_eq!(cursor.position() as usize, bytes.len());
+
+        frame
+    }
+
+    #[test]
+    fn frames_round_trip_through_encode() {
+        for seed in 0..4 {
+            let frame = get_test_frame(seed);
+            let decoded = decode(&frame.encode().unwrap(), None);
+
+            //encoding marks the frame as self-contained, the colors stay the same.
+            let mut header = frame.get_header().clone();
+            header.set_frame_type(PPMFrameType::Normal);
+
+            assert_eq!(decoded.get_header().header, header.header);
+            assert_eq!(
+                decoded.get_indexed_pixels().unwrap(),
+                frame.get_indexed_pixels().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn clearing_the_translation_clears_both_bits() {
+        //files can set either translate bit, or both.
+        let mut header = PPMFrameHeader { header: 0xE1 };
+        assert!(header.get_is_translated());
+
+        header.set_is_translated(false);
+        assert!(!header.get_is_translated());
+        assert_eq!(header.header, 0x81);
+
+        header.set_is_translated(true);
+        assert_eq!(header.header, 0xA1);
+
+        //a frame read with both bits set is encoded without translation bytes.
+        let mut frame = get_test_frame(0);
+        frame.get_header_mut().header |= 0x60;
+
+        let decoded = decode(&frame.encode().unwrap(), None);
+
+        assert!(!decoded.get_header().get_is_translated());
+        assert_eq!(
+            decoded.get_indexed_pixels().unwrap(),
+            frame.get_indexed_pixels().unwrap()
+        );
+    }
+
+    #[test]
+    fn images_pick_paper_and_layers_by_color_count() {
+        //black paper with 200 blue pixels and 5 red ones, so blue goes on layer 1.
+        let get_color = |x: usize, y: usize| match (x, y) {
+            (0..20, 0..10) => PPM_COLOR_BLUE,
+            (100..105, 50) => PPM_COLOR_RED,
+            _ => PPM_PAPER_COLORS[1],
+        };
+
+        let pixels = (0..192)
+            .flat_map(|y| (0..256).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let color = get_color(x, y);
+                [color.r, color.g, color.b, 255]
+            })
+            .collect::<Vec<u8>>();
+
+        let options = FrameImportOptions {
+            dither_type: None,
+            ..Default::default()
+        };
+
+        let frame = PPMFrame::from_image(
+            &ImageWrapper::from_raw_pixels(256, 192, pixels).unwrap(),
+            options,
+        )
+        .unwrap();
+
+        let header = frame.get_header();
+        assert_eq!(header.get_paper_color(), PPMPaperColor::from(1));
+        assert_eq!(header.get_layer_color(1).unwrap(), PPMLayerColor::Blue);
+        assert_eq!(header.get_layer_color(2).unwrap(), PPMLayerColor::Red);
+
+        let expected = (0..192)
+            .flat_map(|y| (0..256).map(move |x| (x, y)))
+            .map(|(x, y)| match get_color(x, y) {
+                color if color == PPM_COLOR_BLUE => 1,
+                color if color == PPM_COLOR_RED => 2,
+                _ => 0,
+            })
+            .collect::<Vec<u8>>();
+
+        assert_eq!(frame.get_indexed_pixels().unwrap(), expected);
+    }
+
+    /// The previous frame moved by `translation`, with a small scribble added on layer 1.
+    fn get_moved_frame(previous_frame: &PPMFrame, translation: (isize, isize)) -> PPMFrame {
+        let mut frame = previous_frame.clone();
+
+        for layer_index in 1..=2 {
+            for y in 0..192isize {
+                for x in 0..256isize {
+                    let (source_x, source_y) = (x - translation.0, y - translation.1);
+
+                    let value = (0..256).contains(&source_x)
+                        && (0..192).contains(&source_y)
+                        && previous_frame
+                            .get_layer(layer_index)
+                            .unwrap()
+                            .get(source_x as usize, source_y as usize)
+                            .unwrap();
+
+                    frame
+                        .get_layer_mut(layer_index)
+                        .unwrap()
+                        .set(x as usize, y as usize, value)
+                        .unwrap();
                 }
             }
         }
 
-        Ok(image)
+        for x in 40..60 {
+            frame.get_layer_mut(1).unwrap().set(x, 100, true).unwrap();
+        }
+
+        frame
+    }
+
+    #[test]
+    fn diffed_frames_round_trip_on_top_of_the_previous_frame() {
+        let previous_frame = get_test_frame(2);
+        let frame = get_moved_frame(&previous_frame, (5, -3));
+
+        assert_eq!(frame.find_best_translation(&previous_frame), (5, -3));
+
+        let encoded = frame.encode_with_previous(Some(&previous_frame)).unwrap();
+
+        assert!(encoded.len() < frame.encode().unwrap().len());
+
+        let decoded = decode(&encoded, Some(previous_frame.clone()));
+
+        assert_eq!(decoded.get_header().get_frame_type(), PPMFrameType::Diffed);
+        assert_eq!(decoded.get_translation(), (5, -3));
+        assert_eq!(
+            decoded.get_indexed_pixels().unwrap(),
+            frame.get_indexed_pixels().unwrap()
+        );
+
+        //without translation the frame still decodes the same, just larger.
+        let untranslated = frame.encode_diffed(&previous_frame, 0, 0).unwrap();
+
+        assert!(untranslated.len() > encoded.len());
+        assert_eq!(
+            decode(&untranslated, Some(previous_frame))
+                .get_indexed_pixels()
+                .unwrap(),
+            frame.get_indexed_pixels().unwrap()
+        );
     }
 }
@@ -91,7 +91,8 @@ impl PPMFrameHeader {
             false => 0,
         };
 
-        self.header = (self.header & 0xDF) | (value << 5);
+        //both translate bits count, so both are cleared.
+        self.header = (self.header & 0x9F) | (value << 5);
     }
 
     pub fn get_paper_color(&self) -> PPMPaperColor {
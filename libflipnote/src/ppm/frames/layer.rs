@@ -35,6 +35,26 @@ impl PPMLayer {
         Ok(())
     }
 
+    /// Packs the best encoding of every line into the 0x30 byte table that precedes the line data, 4 lines per byte.
+    pub fn get_packed_encodings(&self) -> [u8; 0x30] {
+        let mut encodings_compressed = [0u8; 0x30];
+
+        for (i, line) in self.lines.iter().enumerate() {
+            let encoding: u8 = line.get_best_encoding().into();
+
+            encodings_compressed[i / 4] |= encoding << ((i % 4) * 2);
+        }
+
+        encodings_compressed
+    }
+
+    pub fn encode(&self, cursor: &mut Cursor<&mut Vec<u8>>) -> Result<()> {
+        for line in self.lines.iter() {
+            line.encode(cursor, line.get_best_encoding())?;
+        }
+        Ok(())
+    }
+
     pub fn get_data(&self) -> Result<Vec<u8>> {
         let mut data = vec![0u8; 256 * 192];
 
@@ -69,3 +89,42 @@ impl PPMLayer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::get_test_frame;
+
+    #[test]
+    fn layers_round_trip_with_their_best_encodings() {
+        let frame = get_test_frame(3);
+
+        for layer_index in 1..=2 {
+            let layer = frame.get_layer(layer_index).unwrap();
+
+            let mut bytes = Vec::new();
+            layer.encode(&mut Cursor::new(&mut bytes)).unwrap();
+
+            let encodings = layer
+                .lines
+                .iter()
+                .map(|line| line.get_best_encoding())
+                .collect::<Vec<_>>();
+
+            //the packed table holds the same encodings, 4 lines per byte with the first in the lowest bits.
+            let packed = layer.get_packed_encodings();
+
+            for (i, encoding) in encodings.iter().enumerate() {
+                assert_eq!(
+                    LineEncoding::from(packed[i / 4] >> ((i % 4) * 2) & 0x3),
+                    *encoding
+                );
+            }
+
+            let mut decoded = PPMLayer::new(&encodings);
+            decoded.parse(&mut Cursor::new(bytes.as_slice())).unwrap();
+
+            assert_eq!(decoded.get_data().unwrap(), layer.get_data().unwrap());
+        }
+    }
+}
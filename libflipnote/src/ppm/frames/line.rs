@@ -1,9 +1,9 @@
-//! Line decoding code adapted from the [PPM File Format Documentation](https://github.com/Flipnote-Collective/flipnote-studio-docs/wiki/PPM-format#line-compression) by the Flipnote Collective.
+//! Line decoding and encoding code adapted from the [PPM File Format Documentation](https://github.com/Flipnote-Collective/flipnote-studio-docs/wiki/PPM-format#line-compression) by the Flipnote Collective.
 
-use std::io::Cursor;
+use std::io::{Cursor, Write};
 
 use anyhow::{Result, ensure};
-use binrw::BinReaderExt;
+use binrw::{BinReaderExt, BinWriterExt};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum LineEncoding {
@@ -25,6 +25,17 @@ impl From<u8> for LineEncoding {
     }
 }
 
+impl From<LineEncoding> for u8 {
+    fn from(value: LineEncoding) -> Self {
+        match value {
+            LineEncoding::Skip => 0,
+            LineEncoding::Coded => 1,
+            LineEncoding::InvertedCoded => 2,
+            LineEncoding::Raw => 3,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PPMLine {
     data: Vec<u8>,
@@ -119,4 +130,156 @@ impl PPMLine {
 
         Ok(())
     }
+
+    /// Packs the line into 32 chunks of 8 pixels, least significant bit first.
+    pub fn get_chunks(&self) -> [u8; 32] {
+        let mut chunks = [0u8; 32];
+
+        for (i, pixel) in self.data.iter().enumerate() {
+            chunks[i / 8] |= (pixel & 0x1) << (i % 8);
+        }
+
+        chunks
+    }
+
+    /// Returns the encoding that stores this line in the fewest bytes.
+    pub fn get_best_encoding(&self) -> LineEncoding {
+        let chunks = self.get_chunks();
+
+        let coded_size = chunks.iter().filter(|c| **c != 0x00).count();
+        let inverted_size = chunks.iter().filter(|c| **c != 0xFF).count();
+
+        if coded_size == 0 {
+            return LineEncoding::Skip;
+        }
+
+        //both coded encodings spend 4 bytes on the chunk flags, raw lines are always 32 bytes.
+        match coded_size.min(inverted_size) + 4 {
+            size if size >= 32 => LineEncoding::Raw,
+            _ if inverted_size < coded_size => LineEncoding::InvertedCoded,
+            _ => LineEncoding::Coded,
+        }
+    }
+
+    pub fn encode(&self, cursor: &mut Cursor<&mut Vec<u8>>, encoding: LineEncoding) -> Result<()> {
+        match encoding {
+            LineEncoding::Skip => Ok(()),
+            LineEncoding::Coded => self.write_coded_line(cursor, false),
+            LineEncoding::InvertedCoded => self.write_coded_line(cursor, true),
+            LineEncoding::Raw => self.write_raw_line(cursor),
+        }
+    }
+
+    pub fn write_coded_line(
+        &self,
+        cursor: &mut Cursor<&mut Vec<u8>>,
+        inverted: bool,
+    ) -> Result<()> {
+        let chunks = self.get_chunks();
+
+        //inverted lines start out filled, so only chunks that aren't fully set need storing.
+        let empty_chunk = match inverted {
+            true => 0xFF,
+            false => 0x00,
+        };
+
+        let mut chunk_flags = 0u32;
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            if *chunk != empty_chunk {
+                chunk_flags |= 0x80000000 >> i;
+            }
+        }
+
+        cursor.write_be(&chunk_flags)?;
+
+        for chunk in chunks.iter().filter(|c| **c != empty_chunk) {
+            cursor.write_le(chunk)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_raw_line(&self, cursor: &mut Cursor<&mut Vec<u8>>) -> Result<()> {
+        cursor.write_all(&self.get_chunks())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_test_line(pixels: impl Fn(usize) -> bool) -> PPMLine {
+        let mut line = PPMLine::default();
+
+        for x in 0..256 {
+            line.set(x, pixels(x) as u8).unwrap();
+        }
+
+        line
+    }
+
+    fn decode(bytes: &[u8], encoding: LineEncoding) -> PPMLine {
+        let mut line = PPMLine::new(encoding);
+        let mut cursor = Cursor::new(bytes);
+
+        line.parse(&mut cursor).unwrap();
+
+        assert_eq!(cursor.position() as usize, bytes.len());
+
+        line
+    }
+
+    #[test]
+    fn lines_round_trip_in_every_encoding() {
+        let lines = [
+            get_test_line(|_| false),
+            get_test_line(|x| x == 0 || x == 100 || x == 255),
+            get_test_line(|x| x != 37),
+            get_test_line(|x| (x * 7 + x / 3) % 5 < 2),
+        ];
+
+        for line in lines.iter() {
+            for encoding in [
+                LineEncoding::Coded,
+                LineEncoding::InvertedCoded,
+                LineEncoding::Raw,
+            ] {
+                let mut bytes = Vec::new();
+                line.encode(&mut Cursor::new(&mut bytes), encoding).unwrap();
+
+                assert_eq!(decode(&bytes, encoding).get_data(), line.get_data());
+            }
+        }
+    }
+
+    #[test]
+    fn best_encoding_is_the_smallest() {
+        assert_eq!(
+            get_test_line(|_| false).get_best_encoding(),
+            LineEncoding::Skip
+        );
+        assert_eq!(
+            get_test_line(|x| x == 100).get_best_encoding(),
+            LineEncoding::Coded
+        );
+        assert_eq!(
+            get_test_line(|x| x != 100).get_best_encoding(),
+            LineEncoding::InvertedCoded
+        );
+        assert_eq!(
+            get_test_line(|x| x % 2 == 0).get_best_encoding(),
+            LineEncoding::Raw
+        );
+
+        //a coded line with one chunk is the 4 byte chunk flags and that chunk.
+        let mut bytes = Vec::new();
+        get_test_line(|x| x == 100)
+            .encode(&mut Cursor::new(&mut bytes), LineEncoding::Coded)
+            .unwrap();
+
+        assert_eq!(bytes, [0x00, 0x08, 0x00, 0x00, 0x10]);
+    }
 }
@@ -1,6 +1,10 @@
 pub mod animation_data;
 pub mod animation_flags;
+pub mod debug_render;
 pub mod frame;
 pub mod frame_header;
 pub mod layer;
 pub mod line;
+pub mod onion_skin;
+pub mod render_options;
+pub mod render_sink;
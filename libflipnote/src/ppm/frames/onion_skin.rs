@@ -0,0 +1,311 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, ensure};
+
+use crate::utils::image_utils::{ImageWrapper, RgbWrapper};
+
+use super::{frame::PPMFrame, render_options::RenderOptions};
+
+/// Controls how [`OnionSkinRenderer`] draws the neighbouring frames.
+#[derive(Debug, Clone)]
+pub struct OnionSkinOptions {
+    /// How many earlier frames are shown.
+    pub frames_before: usize,
+    /// How many later frames are shown.
+    pub frames_after: usize,
+    pub before_tint: RgbWrapper,
+    pub after_tint: RgbWrapper,
+    /// Opacity of the closest ghosts from 0 to 1, fading out linearly with every frame further away.
+    pub opacity: f32,
+    /// Takes the ghosts from the other end of the flipnote near its start and end, for looping animations.
+    pub wrap: bool,
+    pub render_options: RenderOptions,
+}
+
+impl Default for OnionSkinOptions {
+    fn default() -> Self {
+        Self {
+            frames_before: 2,
+            frames_after: 2,
+            before_tint: RgbWrapper::new(255, 64, 64),
+            after_tint: RgbWrapper::new(64, 160, 255),
+            opacity: 0.4,
+            wrap: false,
+            render_options: RenderOptions::default(),
+        }
+    }
+}
+
+/// Draws a frame over tinted, semi-transparent ghosts of the frames around it.
+/// Rendered frames are kept while they are still needed, so going through the frames in order renders each of them once.
+pub struct OnionSkinRenderer<'a> {
+    frames: &'a [PPMFrame],
+    options: OnionSkinOptions,
+    cache: BTreeMap<usize, Vec<u8>>,
+}
+
+impl<'a> OnionSkinRenderer<'a> {
+    pub fn new(frames: &'a [PPMFrame], options: OnionSkinOptions) -> Result<Self> {
+        options.render_options.validate()?;
+        ensure!(
+            (0.0..=1.0).contains(&options.opacity),
+            "Onion skin opacity must be between 0 and 1"
+        );
+
+        Ok(Self {
+            frames,
+            options,
+            cache: BTreeMap::new(),
+        })
+    }
+
+    /// Renders frame `index` with its ghosts. The ghosts only show through the paper, the frame's own ink stays on top.
+    pub fn get_image(&mut self, index: usize) -> Result<ImageWrapper> {
+        ensure!(
+            index < self.frames.len(),
+            "Frame index {} out of bounds",
+            index
+        );
+
+        let render_options = &self.options.render_options;
+        let (width, height) = (render_options.get_width(), render_options.get_height());
+
+        let mut pixels = self.frames[index]
+            .get_image_with(render_options)?
+            .get_raw_pixels();
+
+        let ghosts = self.get_ghosts(index);
+
+        //keeps what this frame and the next one need.
+        let needed = ghosts
+            .iter()
+            .chain(self.get_ghosts(index + 1).iter())
+            .map(|(ghost_index, _, _)| *ghost_index)
+            .chain([index, index + 1])
+            .collect::<Vec<usize>>();
+        self.cache.retain(|cached, _| needed.contains(cached));
+
+        let indices = self.get_indices(index)?.clone();
+
+        //the farthest ghosts are drawn first, so closer ones end up on top.
+        for (ghost_index, distance, tint) in ghosts.into_iter().rev() {
+            let opacity = self.get_opacity(distance, tint);
+            let tint = match tint {
+                Tint::Before => self.options.before_tint,
+                Tint::After => self.options.after_tint,
+            };
+
+            let ghost = self.get_indices(ghost_index)?;
+
+            for (i, (ghost_ink, frame_ink)) in ghost.iter().zip(indices.iter()).enumerate() {
+                if *ghost_ink == 0 || *frame_ink != 0 {
+                    continue;
+                }
+
+                blend(&mut pixels[i * 4..i * 4 + 4], &tint, opacity);
+            }
+        }
+
+        ImageWrapper::from_raw_pixels(width, height, pixels)
+    }
+
+    /// Returns the ghosts of a frame as (frame index, distance, tint), closest first. `index` may be one past the last frame.
+    fn get_ghosts(&self, index: usize) -> Vec<(usize, usize, Tint)> {
+        let count = self.frames.len() as isize;
+        let mut ghosts = Vec::new();
+
+        let mut push = |offset: isize, tint: Tint| {
+            let ghost = index as isize + offset;
+
+            let ghost = match (0..count).contains(&ghost) {
+                true => ghost,
+                false if self.options.wrap => ghost.rem_euclid(count),
+                false => return,
+            };
+
+            //short flipnotes can wrap onto the frame itself.
+            if ghost as usize != index {
+                ghosts.push((ghost as usize, offset.unsigned_abs(), tint));
+            }
+        };
+
+        for distance in 1..=self.options.frames_before.max(self.options.frames_after) {
+            if distance <= self.options.frames_before {
+                push(-(distance as isize), Tint::Before);
+            }
+            if distance <= self.options.frames_after {
+                push(distance as isize, Tint::After);
+            }
+        }
+
+        ghosts
+    }
+
+    fn get_opacity(&self, distance: usize, tint: Tint) -> f32 {
+        let count = match tint {
+            Tint::Before => self.options.frames_before,
+            Tint::After => self.options.frames_after,
+        };
+
+        self.options.opacity * (count + 1 - distance) as f32 / count as f32
+    }
+
+    fn get_indices(&mut self, index: usize) -> Result<&Vec<u8>> {
+        if !self.cache.contains_key(&index) {
+            let indices =
+                self.frames[index].get_indexed_pixels_with(&self.options.render_options)?;
+            self.cache.insert(index, indices);
+        }
+
+        Ok(&self.cache[&index])
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Tint {
+    Before,
+    After,
+}
+
+/// Draws `color` over an RGBA pixel with the given opacity.
+fn blend(pixel: &mut [u8], color: &RgbWrapper, opacity: f32) {
+    let alpha = pixel[3] as f32 / 255.0;
+    let out_alpha = opacity + alpha * (1.0 - opacity);
+
+    if out_alpha <= 0.0 {
+        return;
+    }
+
+    for (channel, value) in pixel[..3].iter_mut().zip([color.r, color.g, color.b]) {
+        let mixed =
+            (value as f32 * opacity + *channel as f32 * alpha * (1.0 - opacity)) / out_alpha;
+
+        *channel = mixed.round() as u8;
+    }
+
+    pixel[3] = (out_alpha * 255.0).round() as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppm::frames::{frame_header::PPMPaperColor, render_options::PaperMode};
+
+    /// Empty frames on white paper, with ink at (5, 5) on the frames listed in `inked`.
+    fn get_frames(count: usize, inked: &[usize]) -> Vec<PPMFrame> {
+        (0..count)
+            .map(|i| {
+                let mut frame = PPMFrame::default();
+                frame.get_header_mut().set_paper_color(PPMPaperColor::White);
+
+                if inked.contains(&i) {
+                    frame.get_layer_mut(1).unwrap().set(5, 5, true).unwrap();
+                }
+
+                frame
+            })
+            .collect()
+    }
+
+    /// The ghosts as (frame index, distance, whether they come before).
+    fn get_ghosts(renderer: &OnionSkinRenderer, index: usize) -> Vec<(usize, usize, bool)> {
+        renderer
+            .get_ghosts(index)
+            .into_iter()
+            .map(|(ghost, distance, tint)| (ghost, distance, matches!(tint, Tint::Before)))
+            .collect()
+    }
+
+    #[test]
+    fn ghosts_wrap_around_only_when_asked() {
+        let frames = get_frames(4, &[]);
+        let mut options = OnionSkinOptions {
+            frames_before: 2,
+            frames_after: 1,
+            ..Default::default()
+        };
+
+        let renderer = OnionSkinRenderer::new(&frames, options.clone()).unwrap();
+
+        assert_eq!(get_ghosts(&renderer, 0), [(1, 1, false)]);
+        assert_eq!(get_ghosts(&renderer, 3), [(2, 1, true), (1, 2, true)]);
+
+        options.wrap = true;
+        let renderer = OnionSkinRenderer::new(&frames, options.clone()).unwrap();
+
+        assert_eq!(
+            get_ghosts(&renderer, 0),
+            [(3, 1, true), (1, 1, false), (2, 2, true)]
+        );
+        assert_eq!(
+            get_ghosts(&renderer, 3),
+            [(2, 1, true), (0, 1, false), (1, 2, true)]
+        );
+
+        //2 frames away in a flipnote of 2 frames is the frame itself.
+        let frames = get_frames(2, &[]);
+        let renderer = OnionSkinRenderer::new(&frames, options).unwrap();
+
+        assert_eq!(get_ghosts(&renderer, 0), [(1, 1, true), (1, 1, false)]);
+    }
+
+    #[test]
+    fn ghosts_fade_with_distance() {
+        let frames = get_frames(1, &[]);
+        let options = OnionSkinOptions {
+            frames_before: 2,
+            frames_after: 1,
+            opacity: 0.4,
+            ..Default::default()
+        };
+
+        let renderer = OnionSkinRenderer::new(&frames, options).unwrap();
+
+        assert_eq!(renderer.get_opacity(1, Tint::Before), 0.4);
+        assert_eq!(renderer.get_opacity(2, Tint::Before), 0.2);
+        assert_eq!(renderer.get_opacity(1, Tint::After), 0.4);
+
+        let options = OnionSkinOptions {
+            opacity: 1.5,
+            ..Default::default()
+        };
+        assert!(OnionSkinRenderer::new(&frames, options).is_err());
+    }
+
+    #[test]
+    fn ghosts_are_tinted_through_the_paper() {
+        //frame 1 has ink where frame 0 and 2 don't, frame 3 has it too.
+        let frames = get_frames(4, &[1, 3]);
+        let mut options = OnionSkinOptions {
+            frames_before: 1,
+            frames_after: 1,
+            opacity: 0.5,
+            ..Default::default()
+        };
+
+        let get_pixel = |renderer: &mut OnionSkinRenderer, index: usize| {
+            renderer.get_image(index).unwrap().get_raw_pixels()[(5 * 256 + 5) * 4..][..4].to_vec()
+        };
+
+        let mut renderer = OnionSkinRenderer::new(&frames, options.clone()).unwrap();
+
+        //half of the after tint over white paper.
+        assert_eq!(get_pixel(&mut renderer, 0), [160, 208, 255, 255]);
+        //the frame's own ink stays on top.
+        assert_eq!(get_pixel(&mut renderer, 1), [14, 14, 14, 255]);
+        //frame 1 and 3 are both 1 away from frame 2, the earlier one is drawn on top.
+        assert_eq!(get_pixel(&mut renderer, 2), [208, 136, 160, 255]);
+
+        //going through the frames in order only keeps the frames still needed.
+        assert_eq!(
+            renderer.cache.keys().copied().collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+
+        options.render_options.paper = PaperMode::Transparent;
+        let mut renderer = OnionSkinRenderer::new(&frames, options).unwrap();
+
+        //on transparent paper the ghost is the tint at half opacity.
+        assert_eq!(get_pixel(&mut renderer, 0), [64, 160, 255, 128]);
+    }
+}
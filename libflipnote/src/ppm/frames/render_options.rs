@@ -0,0 +1,140 @@
+use anyhow::{Result, ensure};
+
+use crate::{
+    ppm::palette::{LcdEmulation, Palette},
+    utils::{image_utils::RgbWrapper, upscale_utils::Upscaler},
+};
+
+/// What is drawn where neither layer has ink.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum PaperMode {
+    /// The paper color from the frame header, in the colors of the palette.
+    #[default]
+    Frame,
+    /// Fully transparent, so the layers can be composited over other content.
+    Transparent,
+    /// A fixed color for every frame. Layers drawn in the inverse of the paper still follow the frame's own paper.
+    Color(RgbWrapper),
+}
+
+/// Controls how [`PPMFrame::get_image_with`](super::frame::PPMFrame::get_image_with) and the exporters render frames.
+/// The default renders exactly like [`PPMFrame::get_image`](super::frame::PPMFrame::get_image).
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Whole factor the 256x192 frame is enlarged by.
+    pub scale: u32,
+    pub upscaler: Upscaler,
+    /// Shows or hides layer 1 whatever the flipnote says, `None` follows its hide flag.
+    pub layer_1_visible: Option<bool>,
+    /// Shows or hides layer 2 whatever the flipnote says, `None` follows its hide flag.
+    pub layer_2_visible: Option<bool>,
+    pub paper: PaperMode,
+    pub palette: Palette,
+    /// Makes the frames look like they do on the DSi's screen.
+    /// Formats that store palette indices (GIF, SVG, the HTML player) get its colors but not its subpixels.
+    pub lcd_emulation: Option<LcdEmulation>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1,
+            upscaler: Upscaler::default(),
+            layer_1_visible: None,
+            layer_2_visible: None,
+            paper: PaperMode::default(),
+            palette: Palette::default(),
+            lcd_emulation: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn get_width(&self) -> u32 {
+        256 * self.scale
+    }
+
+    pub fn get_height(&self) -> u32 {
+        192 * self.scale
+    }
+
+    pub fn get_transparent_paper(&self) -> bool {
+        matches!(self.paper, PaperMode::Transparent)
+    }
+
+    /// The same options at 256x192, for work that doesn't depend on the size like comparing frames.
+    pub fn unscaled(&self) -> Self {
+        Self {
+            scale: 1,
+            ..self.clone()
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(self.scale > 0, "Scale must be at least 1");
+        //keeps every size within what the image formats can store.
+        ensure!(self.scale <= 32, "Scale can be at most 32");
+
+        if let Some(lcd_emulation) = &self.lcd_emulation {
+            lcd_emulation.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_sets_the_size_and_must_be_in_range() {
+        let mut options = RenderOptions {
+            scale: 3,
+            ..Default::default()
+        };
+
+        assert_eq!((options.get_width(), options.get_height()), (768, 576));
+
+        let unscaled = options.unscaled();
+        assert_eq!((unscaled.get_width(), unscaled.get_height()), (256, 192));
+
+        for (scale, valid) in [(0, false), (1, true), (32, true), (33, false)] {
+            options.scale = scale;
+            assert_eq!(options.validate().is_ok(), valid, "{scale}");
+        }
+    }
+
+    #[test]
+    fn unscaled_keeps_the_other_options() {
+        let options = RenderOptions {
+            scale: 4,
+            upscaler: Upscaler::Scale2x,
+            layer_2_visible: Some(false),
+            paper: PaperMode::Transparent,
+            palette: Palette::saturated(),
+            ..Default::default()
+        };
+
+        let unscaled = options.unscaled();
+
+        assert_eq!(unscaled.upscaler, Upscaler::Scale2x);
+        assert_eq!(unscaled.layer_2_visible, Some(false));
+        assert_eq!(unscaled.palette, Palette::saturated());
+        assert!(unscaled.get_transparent_paper());
+        assert!(!RenderOptions::default().get_transparent_paper());
+    }
+
+    #[test]
+    fn invalid_lcd_emulation_is_rejected() {
+        let options = RenderOptions {
+            lcd_emulation: Some(LcdEmulation {
+                saturation: 1.5,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert!(options.validate().is_err());
+    }
+}
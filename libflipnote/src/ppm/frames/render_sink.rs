@@ -0,0 +1,331 @@
+use anyhow::{Result, ensure};
+
+use crate::{ppm::palette::LcdEmulation, utils::image_utils::RgbWrapper};
+
+/// What a sink is told before the first row of a frame.
+#[derive(Debug, Clone)]
+pub struct RenderInfo {
+    pub width: u32,
+    pub height: u32,
+    /// How many pixels wide and tall a pixel of the frame became.
+    pub scale: u32,
+    /// Paper, layer 1 and layer 2, in the palette of the options and with the color response of the LCD emulation.
+    pub colors: [RgbWrapper; 3],
+    pub transparent_paper: bool,
+    pub lcd_emulation: Option<LcdEmulation>,
+}
+
+/// One row of a rendered frame.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderRow<'a> {
+    pub y: u32,
+    /// 0 for paper, 1 for layer 1 and 2 for layer 2, as seen with layer 1 on top.
+    pub indices: &'a [u8],
+    /// 1 wherever each layer has ink, including under layer 1. Empty unless the sink asks for it with [`RenderSink::needs_layer_ink`].
+    pub layer_ink: [&'a [u8]; 2],
+}
+
+/// Receives a frame row by row from [`PPMFrame::render_to`](super::frame::PPMFrame::render_to), so it can be stored or streamed in whatever form the consumer needs.
+pub trait RenderSink {
+    fn begin(&mut self, info: &RenderInfo) -> Result<()>;
+
+    fn write_row(&mut self, row: &RenderRow) -> Result<()>;
+
+    /// Whether the rows should carry [`RenderRow::layer_ink`], which costs an extra pass per layer.
+    fn needs_layer_ink(&self) -> bool {
+        false
+    }
+}
+
+/// Collects the palette indices of every pixel, row by row.
+#[derive(Debug, Clone, Default)]
+pub struct IndexSink {
+    pixels: Vec<u8>,
+}
+
+impl IndexSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn into_pixels(self) -> Vec<u8> {
+        self.pixels
+    }
+}
+
+impl RenderSink for IndexSink {
+    fn begin(&mut self, info: &RenderInfo) -> Result<()> {
+        self.pixels = Vec::with_capacity((info.width * info.height) as usize);
+
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &RenderRow) -> Result<()> {
+        self.pixels.extend_from_slice(row.indices);
+
+        Ok(())
+    }
+}
+
+/// Which pixels of a layer end up in its bitplane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitplaneContent {
+    /// Where the layer can be seen, so layer 2 is cleared under the ink of layer 1.
+    #[default]
+    Visible,
+    /// All ink of the layer, as it is stored in the file.
+    Ink,
+}
+
+/// The order of pixels within a byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitOrder {
+    /// The leftmost pixel is the highest bit, like PBM and most monochrome displays.
+    #[default]
+    MsbFirst,
+    LsbFirst,
+}
+
+/// Packs layer 1 and layer 2 into one bit per pixel each, rows following each other without padding.
+#[derive(Debug, Clone, Default)]
+pub struct BitplaneSink {
+    content: BitplaneContent,
+    bit_order: BitOrder,
+    planes: [Vec<u8>; 2],
+}
+
+impl BitplaneSink {
+    pub fn new(content: BitplaneContent, bit_order: BitOrder) -> Self {
+        Self {
+            content,
+            bit_order,
+            planes: [vec![], vec![]],
+        }
+    }
+
+    /// Returns the planes of layer 1 and layer 2.
+    pub fn into_planes(self) -> [Vec<u8>; 2] {
+        self.planes
+    }
+}
+
+impl RenderSink for BitplaneSink {
+    fn begin(&mut self, info: &RenderInfo) -> Result<()> {
+        //frames are 256 pixels wide times the scale, so rows always end on a byte.
+        ensure!(
+            info.width.is_multiple_of(8),
+            "Bitplane rows must be whole bytes"
+        );
+
+        let size = (info.width * info.height / 8) as usize;
+        self.planes = [Vec::with_capacity(size), Vec::with_capacity(size)];
+
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &RenderRow) -> Result<()> {
+        for (layer, plane) in self.planes.iter_mut().enumerate() {
+            let is_set = |x: usize| match self.content {
+                BitplaneContent::Visible => row.indices[x] == layer as u8 + 1,
+                BitplaneContent::Ink => row.layer_ink[layer][x] == 1,
+            };
+
+            for start in (0..row.indices.len()).step_by(8) {
+                let mut byte = 0u8;
+
+                for bit in 0..8 {
+                    if is_set(start + bit) {
+                        byte |= match self.bit_order {
+                            BitOrder::MsbFirst => 0x80 >> bit,
+                            BitOrder::LsbFirst => 1 << bit,
+                        };
+                    }
+                }
+
+                plane.push(byte);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn needs_layer_ink(&self) -> bool {
+        self.content == BitplaneContent::Ink
+    }
+}
+
+/// Collects tightly packed RGBA pixels, with the subpixels of the LCD emulation if it has any.
+#[derive(Debug, Clone, Default)]
+pub struct RgbaSink {
+    info: Option<RenderInfo>,
+    pixels: Vec<u8>,
+}
+
+impl RgbaSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn into_pixels(self) -> Vec<u8> {
+        self.pixels
+    }
+}
+
+impl RenderSink for RgbaSink {
+    fn begin(&mut self, info: &RenderInfo) -> Result<()> {
+        self.pixels = Vec::with_capacity((info.width * info.height * 4) as usize);
+        self.info = Some(info.clone());
+
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &RenderRow) -> Result<()> {
+        let info = self
+            .info
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Rows were written before begin"))?;
+
+        let paper_alpha = match info.transparent_paper {
+            true => 0,
+            false => 255,
+        };
+
+        for (x, index) in row.indices.iter().enumerate() {
+            let color = &info.colors[*index as usize];
+            let mut rgb = [color.r, color.g, color.b];
+
+            if let Some(lcd_emulation) = &info.lcd_emulation {
+                rgb = lcd_emulation.apply_subpixels(rgb, x as u32, row.y, info.scale);
+            }
+
+            let alpha = match index {
+                0 => paper_alpha,
+                _ => 255,
+            };
+
+            self.pixels
+                .extend_from_slice(&[rgb[0], rgb[1], rgb[2], alpha]);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppm::frames::{
+        frame::PPMFrame,
+        frame_header::{PPMLayerColor, PPMPaperColor},
+        render_options::{PaperMode, RenderOptions},
+    };
+
+    /// White paper with red ink at x 0 and 3 and blue ink at x 0 and 1 of the top row, so the blue pixel at x 0 is under the red one.
+    fn get_frame() -> PPMFrame {
+        let mut frame = PPMFrame::default();
+
+        let header = frame.get_header_mut();
+        header.set_paper_color(PPMPaperColor::White);
+        header.set_layer_color(1, PPMLayerColor::Red).unwrap();
+        header.set_layer_color(2, PPMLayerColor::Blue).unwrap();
+
+        for x in [0, 3] {
+            frame.get_layer_mut(1).unwrap().set(x, 0, true).unwrap();
+        }
+        for x in [0, 1] {
+            frame.get_layer_mut(2).unwrap().set(x, 0, true).unwrap();
+        }
+
+        frame
+    }
+
+    fn get_planes(
+        content: BitplaneContent,
+        bit_order: BitOrder,
+        options: &RenderOptions,
+    ) -> [Vec<u8>; 2] {
+        let mut sink = BitplaneSink::new(content, bit_order);
+        get_frame().render_to(&mut sink, options).unwrap();
+
+        sink.into_planes()
+    }
+
+    #[test]
+    fn bitplanes_pack_pixels_in_bit_order() {
+        let options = RenderOptions::default();
+
+        //the first byte of each plane, for layer 1 and layer 2.
+        let cases = [
+            (BitplaneContent::Visible, BitOrder::MsbFirst, [0x90, 0x40]),
+            (BitplaneContent::Visible, BitOrder::LsbFirst, [0x09, 0x02]),
+            (BitplaneContent::Ink, BitOrder::MsbFirst, [0x90, 0xC0]),
+            (BitplaneContent::Ink, BitOrder::LsbFirst, [0x09, 0x03]),
+        ];
+
+        for (content, bit_order, first_bytes) in cases {
+            let planes = get_planes(content, bit_order, &options);
+
+            for (plane, first_byte) in planes.iter().zip(first_bytes) {
+                assert_eq!(plane.len(), 256 * 192 / 8);
+                assert_eq!(plane[0], first_byte, "{content:?} {bit_order:?}");
+                assert!(plane[1..].iter().all(|byte| *byte == 0));
+            }
+        }
+
+        let options = RenderOptions {
+            scale: 2,
+            ..Default::default()
+        };
+
+        //every pixel becomes 2 bits, on 2 rows of 64 bytes.
+        let [layer_1, _] = get_planes(BitplaneContent::Visible, BitOrder::MsbFirst, &options);
+
+        assert_eq!(layer_1.len(), 512 * 384 / 8);
+        assert_eq!([layer_1[0], layer_1[64]], [0b1100_0011, 0b1100_0011]);
+        assert_eq!(layer_1[128], 0);
+    }
+
+    #[test]
+    fn rgba_pixels_use_the_palette_and_paper_alpha() {
+        let render = |options: &RenderOptions| {
+            let mut sink = RgbaSink::new();
+            get_frame().render_to(&mut sink, options).unwrap();
+
+            sink.into_pixels()
+        };
+
+        let pixels = render(&RenderOptions::default());
+
+        assert_eq!(pixels.len(), 256 * 192 * 4);
+        //red over blue, blue, then paper.
+        assert_eq!(
+            pixels[..12],
+            [255, 42, 42, 255, 10, 57, 255, 255, 255, 255, 255, 255]
+        );
+
+        let pixels = render(&RenderOptions {
+            paper: PaperMode::Transparent,
+            ..Default::default()
+        });
+
+        assert_eq!(pixels[4..12], [10, 57, 255, 255, 255, 255, 255, 0]);
+
+        let pixels = render(&RenderOptions {
+            scale: 4,
+            lcd_emulation: Some(LcdEmulation {
+                gamma: 1.0,
+                saturation: 1.0,
+                subpixels: true,
+            }),
+            ..Default::default()
+        });
+
+        //the paper pixel at x 2 is drawn as red, green and blue stripes and then a darker gap.
+        assert_eq!(
+            pixels[8 * 4..12 * 4],
+            [
+                255, 178, 178, 255, 178, 255, 178, 255, 178, 178, 255, 255, 153, 153, 153, 255
+            ]
+        );
+    }
+}
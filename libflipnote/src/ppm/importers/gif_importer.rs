@@ -0,0 +1,127 @@
+use std::io::Cursor;
+
+use anyhow::{Result, ensure};
+use image::{AnimationDecoder, codecs::gif::GifDecoder};
+
+use crate::{
+    ppm::{
+        audio::audio_header::PPMAudioHeader, constants::PPM_FRAMERATE, file::PPMFile,
+        frames::frame::PPMFrame,
+    },
+    utils::image_utils::ImageWrapper,
+};
+
+use super::{ImportOptions, flipnote_builder};
+
+//browsers play delays shorter than this at 100ms, so GIFs are authored with that in mind.
+const GIF_MINIMUM_DELAY_MS: f32 = 20.0;
+const GIF_DEFAULT_DELAY_MS: f32 = 100.0;
+
+/// Converts an animated GIF into a flipnote.
+/// The GIF is sampled at the speed closest to `framerate`, or to its shortest frame delay if `None`, so frames are duplicated or dropped to keep the timing.
+/// Duplicated frames are encoded as empty diffed frames. The GIF's loop setting overrides [`ImportOptions::loop_animation`].
+pub fn import_gif(
+    bytes: &[u8],
+    framerate: Option<f32>,
+    options: &ImportOptions,
+) -> Result<PPMFile> {
+    let decoder = GifDecoder::new(Cursor::new(bytes))?;
+    let gif_frames = decoder.into_frames().collect_frames()?;
+
+    ensure!(!gif_frames.is_empty(), "The GIF has no frames");
+
+    let delays = gif_frames
+        .iter()
+        .map(|frame| {
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            let delay = numerator as f32 / denominator as f32;
+
+            match delay < GIF_MINIMUM_DELAY_MS {
+                true => GIF_DEFAULT_DELAY_MS,
+                false => delay,
+            }
+        })
+        .collect::<Vec<f32>>();
+
+    let framerate =
+        framerate.unwrap_or_else(|| 1000.0 / delays.iter().copied().fold(f32::MAX, f32::min));
+
+    let speed = PPMAudioHeader::get_speed_for_framerate(framerate);
+    let frame_duration = 1000.0 / PPM_FRAMERATE[speed as usize];
+
+    let converted_frames = gif_frames
+        .into_iter()
+        .map(|frame| {
+            let buffer = frame.into_buffer();
+            let image =
+                ImageWrapper::from_raw_pixels(buffer.width(), buffer.height(), buffer.into_raw())?;
+
+            PPMFrame::from_image(&image, options.frame_options.clone())
+        })
+        .collect::<Result<Vec<PPMFrame>>>()?;
+
+    //samples the GIF timeline at the start of every flipnote frame.
+    let total_duration: f32 = delays.iter().sum();
+    let frame_count = ((total_duration / frame_duration).round() as usize).max(1);
+
+    let mut frames = Vec::with_capacity(frame_count);
+    let mut gif_index = 0;
+    let mut gif_frame_end = delays[0];
+
+    for i in 0..frame_count {
+        let time = i as f32 * frame_duration;
+
+        while time >= gif_frame_end && gif_index + 1 < delays.len() {
+            gif_index += 1;
+            gif_frame_end += delays[gif_index];
+        }
+
+        frames.push(converted_frames[gif_index].clone());
+    }
+
+    let mut options = options.clone();
+    options.loop_animation = get_gif_loop(bytes)?;
+
+    flipnote_builder(frames, speed, &options).build()
+}
+
+/// Returns whether the GIF repeats, GIFs without a loop extension play once.
+fn get_gif_loop(bytes: &[u8]) -> Result<bool> {
+    let mut decoder = gif::DecodeOptions::new().read_info(Cursor::new(bytes))?;
+
+    //the loop extension comes before the first frame, but is only read once the decoder gets there.
+    decoder.next_frame_info()?;
+
+    Ok(!matches!(decoder.repeat(), gif::Repeat::Finite(0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use gif::{Encoder, Frame, Repeat};
+
+    use super::*;
+
+    fn get_gif(repeat: Option<Repeat>) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut encoder = Encoder::new(&mut bytes, 1, 1, &[0, 0, 0, 255, 255, 255]).unwrap();
+
+        if let Some(repeat) = repeat {
+            encoder.set_repeat(repeat).unwrap();
+        }
+
+        encoder
+            .write_frame(&Frame::from_indexed_pixels(1, 1, vec![0], None))
+            .unwrap();
+        drop(encoder);
+
+        bytes
+    }
+
+    #[test]
+    fn gifs_loop_unless_they_play_once() {
+        assert!(!get_gif_loop(&get_gif(None)).unwrap());
+        assert!(!get_gif_loop(&get_gif(Some(Repeat::Finite(0)))).unwrap());
+        assert!(get_gif_loop(&get_gif(Some(Repeat::Finite(3)))).unwrap());
+        assert!(get_gif_loop(&get_gif(Some(Repeat::Infinite))).unwrap());
+    }
+}
@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, ensure};
+
+use crate::{
+    ppm::{audio::audio_header::PPMAudioHeader, file::PPMFile, frames::frame::PPMFrame},
+    utils::image_utils::ImageWrapper,
+};
+
+use super::{ImportOptions, flipnote_builder};
+
+const IMAGE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+/// Returns every PNG or JPEG image in a directory, sorted by file name.
+pub fn get_image_paths(path: impl Into<PathBuf>) -> Result<Vec<PathBuf>> {
+    let mut paths = std::fs::read_dir(path.into())?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<PathBuf>>>()?;
+
+    paths.retain(|path| {
+        path.extension().is_some_and(|extension| {
+            IMAGE_EXTENSIONS.contains(&extension.to_string_lossy().to_lowercase().as_str())
+        })
+    });
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Converts every image into a frame, in order, and plays them back at the speed closest to `framerate`.
+pub fn import_image_sequence(
+    paths: &[PathBuf],
+    framerate: f32,
+    options: &ImportOptions,
+) -> Result<PPMFile> {
+    ensure!(!paths.is_empty(), "No images to import");
+
+    let frames = paths
+        .iter()
+        .map(|path| PPMFrame::from_image(&ImageWrapper::load(path)?, options.frame_options.clone()))
+        .collect::<Result<Vec<PPMFrame>>>()?;
+
+    let speed = PPMAudioHeader::get_speed_for_framerate(framerate);
+
+    flipnote_builder(frames, speed, options).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::get_temp_dir;
+
+    #[test]
+    fn image_paths_are_sorted_by_file_name() {
+        let dir = get_temp_dir("image-paths");
+
+        for name in [
+            "b.png",
+            "a10.JPG",
+            "a2.jpeg",
+            "notes.txt",
+            "c.gif",
+            "a1.png",
+        ] {
+            std::fs::write(dir.join(name), []).unwrap();
+        }
+
+        let names = get_image_paths(&dir)
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+
+        //names are compared as text, so a10 comes before a2.
+        assert_eq!(names, ["a1.png", "a10.JPG", "a2.jpeg", "b.png"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,49 @@
+//! Importers that turn other formats into brand-new flipnotes.
+
+use super::{
+    file_builder::PPMFileBuilder,
+    frames::frame::{FrameImportOptions, PPMFrame},
+};
+
+pub mod gif_importer;
+pub mod image_sequence_importer;
+pub mod video_importer;
+
+/// Options shared by every importer.
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub frame_options: FrameImportOptions,
+    pub author_name: String,
+    /// The frame the thumbnail is rendered from, clamped to the last frame.
+    pub thumbnail_frame_index: usize,
+    pub loop_animation: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            frame_options: FrameImportOptions::default(),
+            author_name: String::from("Paracule"),
+            thumbnail_frame_index: 0,
+            loop_animation: false,
+        }
+    }
+}
+
+/// Prepares a builder for an unsigned flipnote around the given frames, filled in from the import options.
+pub fn flipnote_builder(
+    frames: Vec<PPMFrame>,
+    speed: u8,
+    options: &ImportOptions,
+) -> PPMFileBuilder {
+    let thumbnail_frame_index = options
+        .thumbnail_frame_index
+        .min(frames.len().saturating_sub(1));
+
+    PPMFileBuilder::new()
+        .author_name(options.author_name.as_str())
+        .frames(frames)
+        .speed(speed)
+        .loop_animation(options.loop_animation)
+        .thumbnail_frame_index(thumbnail_frame_index)
+}
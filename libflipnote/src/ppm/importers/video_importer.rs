@@ -0,0 +1,382 @@
+use std::{
+    io::{ErrorKind, Read},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result, bail, ensure};
+
+use crate::{
+    ppm::{
+        audio::{audio_header::PPMAudioHeader, wav_container::WavContainer},
+        constants::{PPM_AUDIO_PLAYBACK_SAMPLE_RATE, PPM_FRAMERATE, PPM_MAX_FRAME_COUNT},
+        file::PPMFile,
+        frames::frame::{FrameImportOptions, PPMFrame},
+    },
+    utils::image_utils::{ImageWrapper, ResizeMode},
+};
+
+use super::{ImportOptions, flipnote_builder};
+
+/// Options for decoding videos through ffmpeg.
+#[derive(Debug, Clone)]
+pub struct VideoImportOptions {
+    /// The ffmpeg executable, looked up in `PATH` by default.
+    pub ffmpeg_path: PathBuf,
+    /// The ffprobe executable, which checks whether the video has audio. Looked up in `PATH` by default.
+    pub ffprobe_path: PathBuf,
+    /// Imports the first audio track as the BGM. Videos without audio get no BGM.
+    pub import_audio: bool,
+}
+
+impl Default for VideoImportOptions {
+    fn default() -> Self {
+        Self {
+            ffmpeg_path: PathBuf::from("ffmpeg"),
+            ffprobe_path: PathBuf::from("ffprobe"),
+            import_audio: true,
+        }
+    }
+}
+
+/// Converts any video ffmpeg can decode into a flipnote, played back at the speed closest to `framerate`.
+/// ffmpeg samples the video at that speed and fits it into 256x192 following [`FrameImportOptions::resize_mode`].
+/// The soundtrack becomes the BGM, cut to the length of the animation.
+pub fn import_video(
+    path: impl Into<PathBuf>,
+    framerate: f32,
+    video_options: &VideoImportOptions,
+    options: &ImportOptions,
+) -> Result<PPMFile> {
+    let path: PathBuf = path.into();
+
+    let speed = PPMAudioHeader::get_speed_for_framerate(framerate);
+    let framerate = PPM_FRAMERATE[speed as usize];
+
+    let frames = read_video_frames(&path, framerate, video_options, &options.frame_options)?;
+
+    ensure!(!frames.is_empty(), "ffmpeg did not return any frames");
+
+    let duration = frames.len() as f32 / framerate;
+
+    let mut builder = flipnote_builder(frames, speed, options);
+
+    if video_options.import_audio {
+        if let Some(track) = read_audio(&path, duration, video_options)? {
+            builder = builder.background_track(track);
+        }
+    }
+
+    builder.build()
+}
+
+fn get_scale_filter(frame_options: &FrameImportOptions) -> String {
+    let background = frame_options.background_color;
+
+    match frame_options.resize_mode {
+        ResizeMode::Stretch => String::from("scale=256:192"),
+        ResizeMode::Fit => format!(
+            "scale=256:192:force_original_aspect_ratio=decrease,pad=256:192:(ow-iw)/2:(oh-ih)/2:color=0x{:02X}{:02X}{:02X}",
+            background.r, background.g, background.b
+        ),
+        ResizeMode::Fill => {
+            String::from("scale=256:192:force_original_aspect_ratio=increase,crop=256:192")
+        }
+    }
+}
+
+fn read_video_frames(
+    path: &Path,
+    framerate: f32,
+    video_options: &VideoImportOptions,
+    frame_options: &FrameImportOptions,
+) -> Result<Vec<PPMFrame>> {
+    let mut ffmpeg = Command::new(&video_options.ffmpeg_path)
+        .args(["-v", "error", "-nostdin"])
+        .arg("-i")
+        .arg(path)
+        .arg("-an")
+        .arg("-vf")
+        .arg(format!(
+            "fps={},{}",
+            framerate,
+            get_scale_filter(frame_options)
+        ))
+        .args(["-f", "rawvideo"])
+        .args(["-pix_fmt", "rgba"])
+        .arg("pipe:1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to start {}", video_options.ffmpeg_path.display()))?;
+
+    let mut stdout = ffmpeg.stdout.take().context("ffmpeg stdout is not piped")?;
+    let mut stderr = ffmpeg.stderr.take().context("ffmpeg stderr is not piped")?;
+
+    //stderr is drained separately so ffmpeg can't block on a full pipe.
+    let stderr_reader = std::thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stderr.read_to_string(&mut output);
+        output
+    });
+
+    //ffmpeg already did the resizing.
+    let frame_options = FrameImportOptions {
+        resize_mode: ResizeMode::Stretch,
+        ..frame_options.clone()
+    };
+
+    let frames = read_raw_frames(&mut stdout, framerate, &frame_options);
+
+    //ffmpeg is still writing if reading stopped early, it's stopped and waited on either way so it doesn't linger.
+    if frames.is_err() {
+        let _ = ffmpeg.kill();
+    }
+
+    let status = ffmpeg.wait()?;
+    let stderr = stderr_reader.join().unwrap_or_default();
+
+    let frames = frames?;
+
+    if !status.success() {
+        bail!("ffmpeg failed: {:?}\n{}", status, stderr);
+    }
+
+    Ok(frames)
+}
+
+/// Reads 256x192 RGBA frames until ffmpeg closes the pipe.
+fn read_raw_frames(
+    stdout: &mut impl Read,
+    framerate: f32,
+    frame_options: &FrameImportOptions,
+) -> Result<Vec<PPMFrame>> {
+    let mut frames = Vec::new();
+    let mut buffer = vec![0u8; 256 * 192 * 4];
+
+    loop {
+        match stdout.read_exact(&mut buffer) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+
+        ensure!(
+            frames.len() < PPM_MAX_FRAME_COUNT,
+            "The video is longer than {} frames at {} fps",
+            PPM_MAX_FRAME_COUNT,
+            framerate
+        );
+
+        let image = ImageWrapper::from_raw_pixels(256, 192, buffer.clone())?;
+
+        frames.push(PPMFrame::from_image(&image, frame_options.clone())?);
+    }
+
+    Ok(frames)
+}
+
+/// Asks ffprobe whether the video has an audio track, it lists the index of the first one and nothing if there is none.
+fn has_audio(path: &Path, video_options: &VideoImportOptions) -> Result<bool> {
+    let output = Command::new(&video_options.ffprobe_path)
+        .args(["-v", "error"])
+        .args(["-select_streams", "a:0"])
+        .args(["-show_entries", "stream=index"])
+        .args(["-of", "csv=p=0"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("Failed to start {}", video_options.ffprobe_path.display()))?;
+
+    if !output.status.success() {
+        bail!(
+            "ffprobe failed: {:?}\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(!output.stdout.trim_ascii().is_empty())
+}
+
+/// Reads the first audio track, `None` if the video doesn't have one.
+fn read_audio(
+    path: &Path,
+    duration: f32,
+    video_options: &VideoImportOptions,
+) -> Result<Option<WavContainer>> {
+    if !has_audio(path, video_options)? {
+        return Ok(None);
+    }
+
+    let output = Command::new(&video_options.ffmpeg_path)
+        .args(["-v", "error", "-nostdin"])
+        .arg("-i")
+        .arg(path)
+        .arg("-vn")
+        .args(["-map", "0:a:0"])
+        .args(["-t", &duration.to_string()])
+        .args(["-ac", "1"])
+        .args(["-ar", &PPM_AUDIO_PLAYBACK_SAMPLE_RATE.to_string()])
+        .args(["-f", "s16le"])
+        .arg("pipe:1")
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("Failed to start {}", video_options.ffmpeg_path.display()))?;
+
+    if !output.status.success() {
+        bail!(
+            "ffmpeg failed: {:?}\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    //a track that only starts after the animation ends.
+    if output.stdout.is_empty() {
+        return Ok(None);
+    }
+
+    let samples = output
+        .stdout
+        .chunks_exact(2)
+        .map(|c| i16::from_le_bytes([c[0], c[1]]))
+        .collect::<Vec<i16>>();
+
+    Ok(Some(WavContainer::from_samples(
+        samples,
+        1,
+        PPM_AUDIO_PLAYBACK_SAMPLE_RATE,
+        16,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::{get_temp_dir, write_script};
+
+    /// Stands in for ffmpeg and ffprobe, logging their arguments. The video pass writes 3 blank frames, and the audio pass `audio` bytes of samples.
+    /// ffprobe reports an audio track unless `audio` is 0.
+    fn get_video_options(dir: &Path, audio: usize) -> VideoImportOptions {
+        let ffmpeg = write_script(
+            dir,
+            "ffmpeg",
+            &format!(
+                r#"echo "$@" >> "{}"
+case "$*" in
+  *" -an "*) head -c {} /dev/zero ;;
+  *" -vn -map 0:a:0 "*) head -c {audio} /dev/zero ;;
+  *) exit 2 ;;
+esac
+"#,
+                dir.join("ffmpeg_args").display(),
+                3 * 256 * 192 * 4
+            ),
+        );
+
+        let ffprobe = write_script(
+            dir,
+            "ffprobe",
+            &format!(
+                r#"echo "$@" >> "{}"
+[ {audio} -gt 0 ] && echo 1
+exit 0
+"#,
+                dir.join("ffprobe_args").display()
+            ),
+        );
+
+        VideoImportOptions {
+            ffmpeg_path: ffmpeg,
+            ffprobe_path: ffprobe,
+            ..Default::default()
+        }
+    }
+
+    fn import(dir: &Path, video_options: &VideoImportOptions) -> Result<PPMFile> {
+        import_video(
+            dir.join("video.mp4"),
+            30.0,
+            video_options,
+            &ImportOptions::default(),
+        )
+    }
+
+    fn read_args(dir: &Path, name: &str) -> Vec<String> {
+        std::fs::read_to_string(dir.join(name))
+            .unwrap_or_default()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn import_video_without_audio_has_no_bgm() {
+        let dir = get_temp_dir("import-video-without-audio");
+        let file = import(&dir, &get_video_options(&dir, 0)).unwrap();
+
+        assert_eq!(file.get_frame_count(), 3);
+        assert!(file.audio.background_track.is_none());
+
+        //only the video is decoded, after ffprobe found no audio.
+        assert_eq!(read_args(&dir, "ffmpeg_args").len(), 1);
+        assert_eq!(
+            read_args(&dir, "ffprobe_args"),
+            [format!(
+                "-v error -select_streams a:0 -show_entries stream=index -of csv=p=0 {}",
+                dir.join("video.mp4").display()
+            )]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn import_video_with_audio_has_bgm() {
+        let dir = get_temp_dir("import-video-with-audio");
+        let file = import(&dir, &get_video_options(&dir, 2048)).unwrap();
+
+        assert_eq!(file.get_frame_count(), 3);
+        assert!(file.audio.background_track.is_some());
+        assert_eq!(read_args(&dir, "ffmpeg_args").len(), 2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn import_video_without_audio_import_skips_ffprobe() {
+        let dir = get_temp_dir("import-video-no-audio-import");
+        let video_options = VideoImportOptions {
+            import_audio: false,
+            ..get_video_options(&dir, 2048)
+        };
+
+        let file = import(&dir, &video_options).unwrap();
+
+        assert!(file.audio.background_track.is_none());
+        assert!(read_args(&dir, "ffprobe_args").is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn import_video_returns_ffprobe_errors() {
+        let dir = get_temp_dir("import-video-ffprobe-error");
+        let video_options = VideoImportOptions {
+            ffprobe_path: write_script(
+                &dir,
+                "broken_ffprobe",
+                "echo \"Invalid data\" >&2\nexit 1\n",
+            ),
+            ..get_video_options(&dir, 2048)
+        };
+
+        let error = import(&dir, &video_options).unwrap_err();
+
+        assert!(error.to_string().contains("Invalid data"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
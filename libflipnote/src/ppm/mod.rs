@@ -1,7 +1,11 @@
 pub mod audio;
 pub mod constants;
+pub mod exporters;
 pub mod file;
+pub mod file_builder;
 pub mod frames;
+pub mod importers;
+pub mod palette;
 pub mod parsers;
 pub mod thumbnail;
 pub mod writers;
@@ -0,0 +1,324 @@
+//! Colors flipnotes are rendered with, and an optional emulation of the DSi screen.
+
+use anyhow::{Result, ensure};
+
+use crate::utils::{color_utils::hex_to_rgb, image_utils::RgbWrapper};
+
+use super::{
+    constants::{PPM_COLOR_BLUE, PPM_COLOR_RED, PPM_PAPER_COLORS, PPM_THUMBNAIL_COLORS},
+    frames::frame_header::{PPMLayerColor, PPMPaperColor},
+};
+
+/// The colors frames and thumbnails are drawn in. Files only store which color to use, so any palette can be applied to any flipnote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub paper_white: RgbWrapper,
+    pub paper_black: RgbWrapper,
+    pub red: RgbWrapper,
+    pub blue: RgbWrapper,
+    /// The 16 colors thumbnail pixels index into.
+    pub thumbnail: [RgbWrapper; 16],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::flipnote_studio()
+    }
+}
+
+impl Palette {
+    /// The colors Flipnote Studio draws with, the same as [`PPM_PAPER_COLORS`], [`PPM_COLOR_RED`], [`PPM_COLOR_BLUE`] and [`PPM_THUMBNAIL_COLORS`].
+    pub fn flipnote_studio() -> Self {
+        Self {
+            paper_white: PPM_PAPER_COLORS[0],
+            paper_black: PPM_PAPER_COLORS[1],
+            red: PPM_COLOR_RED,
+            blue: PPM_COLOR_BLUE,
+            //the constants are known to be valid.
+            thumbnail: PPM_THUMBNAIL_COLORS.map(|color| hex_to_rgb(color).unwrap()),
+        }
+    }
+
+    /// Pure black, white, red and blue, the brighter look many community players went with.
+    pub fn saturated() -> Self {
+        let mut palette = Self {
+            paper_white: RgbWrapper::new(255, 255, 255),
+            paper_black: RgbWrapper::new(0, 0, 0),
+            red: RgbWrapper::new(255, 0, 0),
+            blue: RgbWrapper::new(0, 0, 255),
+            ..Self::flipnote_studio()
+        };
+
+        palette.thumbnail[4] = palette.red;
+        palette.thumbnail[8] = palette.blue;
+
+        palette
+    }
+
+    /// The Flipnote Studio colors turned into their luminance.
+    pub fn grayscale() -> Self {
+        Self::flipnote_studio().map_colors(|color| {
+            let luma = get_luma(&color).round() as u8;
+
+            RgbWrapper::new(luma, luma, luma)
+        })
+    }
+
+    /// Red and blue replaced with the vermillion and blue of the Okabe-Ito palette, which stay apart with the common kinds of color blindness.
+    pub fn colorblind() -> Self {
+        let mut palette = Self {
+            red: RgbWrapper::new(213, 94, 0),
+            blue: RgbWrapper::new(0, 114, 178),
+            ..Self::flipnote_studio()
+        };
+
+        palette.thumbnail[4] = palette.red;
+        palette.thumbnail[5] = RgbWrapper::new(170, 75, 0);
+        palette.thumbnail[6] = RgbWrapper::new(240, 180, 140);
+        palette.thumbnail[8] = palette.blue;
+        palette.thumbnail[9] = RgbWrapper::new(0, 90, 140);
+        palette.thumbnail[10] = RgbWrapper::new(140, 200, 235);
+
+        palette
+    }
+
+    /// Like [`Palette::colorblind`] with the lighter orange and sky blue of Okabe-Ito, which also stand out on black paper.
+    pub fn colorblind_bright() -> Self {
+        let mut palette = Self {
+            red: RgbWrapper::new(230, 159, 0),
+            blue: RgbWrapper::new(86, 180, 233),
+            ..Self::flipnote_studio()
+        };
+
+        palette.thumbnail[4] = palette.red;
+        palette.thumbnail[5] = RgbWrapper::new(180, 120, 0);
+        palette.thumbnail[6] = RgbWrapper::new(245, 210, 140);
+        palette.thumbnail[8] = palette.blue;
+        palette.thumbnail[9] = RgbWrapper::new(50, 130, 180);
+        palette.thumbnail[10] = RgbWrapper::new(180, 220, 245);
+
+        palette
+    }
+
+    /// Returns the palette with every color passed through `f`.
+    pub fn map_colors(&self, f: impl Fn(RgbWrapper) -> RgbWrapper) -> Self {
+        Self {
+            paper_white: f(self.paper_white),
+            paper_black: f(self.paper_black),
+            red: f(self.red),
+            blue: f(self.blue),
+            thumbnail: self.thumbnail.map(&f),
+        }
+    }
+
+    pub fn get_paper_color(&self, paper_color: &PPMPaperColor) -> RgbWrapper {
+        match paper_color {
+            PPMPaperColor::White => self.paper_white,
+            PPMPaperColor::Black => self.paper_black,
+        }
+    }
+
+    pub fn get_layer_color(
+        &self,
+        layer_color: &PPMLayerColor,
+        paper_color: &PPMPaperColor,
+    ) -> RgbWrapper {
+        match layer_color {
+            PPMLayerColor::InverseOfPaper => match paper_color {
+                PPMPaperColor::White => self.paper_black,
+                PPMPaperColor::Black => self.paper_white,
+            },
+            PPMLayerColor::Red => self.red,
+            PPMLayerColor::Blue => self.blue,
+        }
+    }
+
+    pub fn get_thumbnail_color(&self, index: u8) -> Result<RgbWrapper> {
+        ensure!(index < 16, "Invalid thumbnail color index {}", index);
+
+        Ok(self.thumbnail[index as usize])
+    }
+}
+
+/// Approximates how the DSi's LCD shows colors: washed out, with darker midtones, and with visible subpixels at larger scales.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LcdEmulation {
+    /// Applied to every channel, values above 1 darken the midtones.
+    pub gamma: f32,
+    /// 1 keeps the colors, lower values pull them towards gray.
+    pub saturation: f32,
+    /// Draws every pixel as red, green and blue stripes with a dark gap below and to the right. Needs a scale of 3 or more.
+    pub subpixels: bool,
+}
+
+impl Default for LcdEmulation {
+    fn default() -> Self {
+        Self {
+            gamma: 1.3,
+            saturation: 0.75,
+            subpixels: true,
+        }
+    }
+}
+
+impl LcdEmulation {
+    pub fn validate(&self) -> Result<()> {
+        ensure!(self.gamma > 0.0, "LCD gamma must be positive");
+        ensure!(
+            (0.0..=1.0).contains(&self.saturation),
+            "LCD saturation must be between 0 and 1"
+        );
+
+        Ok(())
+    }
+
+    /// The color response of the screen, without the subpixels. It only depends on the color, so it can be applied to a palette.
+    pub fn apply_color(&self, color: RgbWrapper) -> RgbWrapper {
+        let luma = get_luma(&color);
+
+        let channel = |value: u8| {
+            let value = luma + (value as f32 - luma) * self.saturation;
+
+            ((value / 255.0).clamp(0.0, 1.0).powf(self.gamma) * 255.0).round() as u8
+        };
+
+        RgbWrapper::new(channel(color.r), channel(color.g), channel(color.b))
+    }
+
+    /// Shades a pixel by where it falls inside the `scale` by `scale` block of the frame pixel it belongs to.
+    pub fn apply_subpixels(&self, color: [u8; 3], x: u32, y: u32, scale: u32) -> [u8; 3] {
+        if !self.subpixels || scale < 3 {
+            return color;
+        }
+
+        let (x, y) = (x % scale, y % scale);
+
+        //the gap between pixels.
+        if x == scale - 1 || y == scale - 1 {
+            return color.map(|channel| (channel as f32 * 0.6) as u8);
+        }
+
+        //the lit stripe keeps its channel, the other channels are dimmed.
+        let stripe = (x * 3 / (scale - 1)) as usize;
+
+        let mut shaded = color.map(|channel| (channel as f32 * 0.7) as u8);
+        shaded[stripe] = color[stripe];
+
+        shaded
+    }
+}
+
+/// BT.601 luminance.
+fn get_luma(color: &RgbWrapper) -> f32 {
+    0.299 * color.r as f32 + 0.587 * color.g as f32 + 0.114 * color.b as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppm::frames::{frame::PPMFrame, render_options::RenderOptions};
+
+    #[test]
+    fn presets_keep_colors_in_their_place() {
+        let flipnote_studio = Palette::flipnote_studio();
+
+        assert_eq!(Palette::default(), flipnote_studio);
+        assert_eq!(flipnote_studio.red, PPM_COLOR_RED);
+        assert_eq!(
+            flipnote_studio.get_thumbnail_color(0).unwrap(),
+            hex_to_rgb(PPM_THUMBNAIL_COLORS[0]).unwrap()
+        );
+        assert!(flipnote_studio.get_thumbnail_color(16).is_err());
+
+        //the thumbnail's red and blue follow the layer colors.
+        for palette in [
+            Palette::saturated(),
+            Palette::colorblind(),
+            Palette::colorblind_bright(),
+        ] {
+            assert_eq!(palette.thumbnail[4], palette.red);
+            assert_eq!(palette.thumbnail[8], palette.blue);
+        }
+
+        let grayscale = Palette::grayscale();
+
+        for color in [grayscale.paper_white, grayscale.red, grayscale.blue]
+            .iter()
+            .chain(grayscale.thumbnail.iter())
+        {
+            assert!(color.r == color.g && color.g == color.b);
+        }
+
+        //ink in the inverse of the paper is the other paper color.
+        assert_eq!(
+            grayscale.get_layer_color(&PPMLayerColor::InverseOfPaper, &PPMPaperColor::White),
+            grayscale.paper_black
+        );
+        assert_eq!(
+            grayscale.get_layer_color(&PPMLayerColor::InverseOfPaper, &PPMPaperColor::Black),
+            grayscale.paper_white
+        );
+    }
+
+    #[test]
+    fn lcd_colors_are_washed_out_and_darker() {
+        let lcd_emulation = LcdEmulation::default();
+
+        //black and white stay, midtones get darker.
+        assert_eq!(
+            lcd_emulation.apply_color(RgbWrapper::new(0, 0, 0)),
+            RgbWrapper::new(0, 0, 0)
+        );
+        assert_eq!(
+            lcd_emulation.apply_color(RgbWrapper::new(255, 255, 255)),
+            RgbWrapper::new(255, 255, 255)
+        );
+        assert_eq!(
+            lcd_emulation.apply_color(RgbWrapper::new(128, 128, 128)),
+            RgbWrapper::new(104, 104, 104)
+        );
+
+        //without saturation only the luminance is left.
+        let gray = LcdEmulation {
+            gamma: 1.0,
+            saturation: 0.0,
+            subpixels: false,
+        };
+        assert_eq!(
+            gray.apply_color(PPM_COLOR_RED),
+            RgbWrapper::new(106, 106, 106)
+        );
+
+        let options = RenderOptions {
+            palette: Palette::saturated(),
+            lcd_emulation: Some(gray),
+            ..Default::default()
+        };
+
+        //frames get the palette's colors through the emulation, black paper by default.
+        let mut frame = PPMFrame::default();
+        frame
+            .get_header_mut()
+            .set_layer_color(1, PPMLayerColor::Red)
+            .unwrap();
+
+        assert_eq!(
+            frame.get_palette_with(&options).unwrap(),
+            [
+                RgbWrapper::new(0, 0, 0),
+                RgbWrapper::new(76, 76, 76),
+                RgbWrapper::new(255, 255, 255)
+            ]
+        );
+
+        for (gamma, saturation) in [(0.0, 0.5), (1.0, -0.1), (1.0, 1.1)] {
+            let lcd_emulation = LcdEmulation {
+                gamma,
+                saturation,
+                subpixels: false,
+            };
+
+            assert!(lcd_emulation.validate().is_err());
+        }
+    }
+}
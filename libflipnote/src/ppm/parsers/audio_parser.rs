@@ -76,7 +76,8 @@ fn read_audio_data<T: binrw::io::Read + binrw::io::Seek>(
     Ok(Some(container))
 }
 
-fn mix_audio(audio: &PPMAudio) -> Result<Option<WavContainer>> {
+/// Mixes the BGM and the sound effects, placed on the frames that trigger them, into a single track.
+pub fn mix_audio(audio: &PPMAudio) -> Result<Option<WavContainer>> {
     if audio.background_track.is_none() {
         return Ok(None);
     }
@@ -3,10 +3,9 @@ use std::path::PathBuf;
 use anyhow::Result;
 use binrw::binrw;
 
-use crate::utils::{
-    color_utils::{rgb_to_thumbnail_pixel, thumbnail_pixel_to_rgb},
-    image_utils::ImageWrapper,
-};
+use crate::utils::{color_utils::rgb_to_thumbnail_pixel, image_utils::ImageWrapper};
+
+use super::palette::Palette;
 
 #[binrw]
 #[brw(little)]
@@ -18,10 +17,18 @@ pub struct PPMThumbnailTile {
 
 impl PPMThumbnailTile {
     pub fn get_image(&self) -> Result<ImageWrapper> {
+        self.get_image_with(&Palette::default())
+    }
+
+    pub fn get_image_with(&self, palette: &Palette) -> Result<ImageWrapper> {
         let mut image = ImageWrapper::new(8, 8);
 
         for (i, pixel) in self.pixels.iter().enumerate() {
-            let colors = thumbnail_pixel_to_rgb(*pixel)?;
+            //two pixels per byte, the left one in the low nibble.
+            let colors = (
+                palette.get_thumbnail_color(pixel & 0x0F)?,
+                palette.get_thumbnail_color(pixel >> 4)?,
+            );
 
             let pixel_x = (i % 4) * 2;
             let pixel_y = i / 4;
@@ -44,10 +51,15 @@ pub struct PPMThumbnail {
 
 impl PPMThumbnail {
     pub fn get_image(&self) -> Result<ImageWrapper> {
+        self.get_image_with(&Palette::default())
+    }
+
+    /// Renders the thumbnail in the thumbnail colors of `palette`.
+    pub fn get_image_with(&self, palette: &Palette) -> Result<ImageWrapper> {
         let mut thumbnail = ImageWrapper::new(64, 48);
 
         for (i, tile) in self.tiles.iter().enumerate() {
-            let tile_image = tile.get_image()?;
+            let tile_image = tile.get_image_with(palette)?;
             let tile_x = i % 8;
             let tile_y = i / 8;
 
@@ -78,8 +90,9 @@ impl PPMThumbnail {
             tile.pixels = vec![0; 32];
 
             for (j, tile_pixel) in tile.pixels.iter_mut().enumerate() {
-                let pixel_x = j % 8;
-                let pixel_y = j / 8;
+                //two pixels per byte, 4 bytes per row, like get_image_with reads them.
+                let pixel_x = (j % 4) * 2;
+                let pixel_y = j / 4;
 
                 let pixel1 = image.get_pixel(
                     tile_x as u32 * 8 + pixel_x as u32,
@@ -99,6 +112,11 @@ impl PPMThumbnail {
         Ok(())
     }
 
+    /// Returns true if the thumbnail has all 48 tiles of 32 bytes each.
+    pub fn is_complete(&self) -> bool {
+        self.tiles.len() == 48 && self.tiles.iter().all(|tile| tile.pixels.len() == 32)
+    }
+
     pub fn set_image_from_path(&mut self, path: impl Into<PathBuf>) -> Result<()> {
         let image = ImageWrapper::load(path)?;
 
@@ -107,3 +125,31 @@ impl PPMThumbnail {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_image_round_trips_through_get_image() {
+        let palette = Palette::default();
+        let mut image = ImageWrapper::new(64, 48);
+
+        //every pixel gets a different color than its neighbours, so swapped or shifted pixels show up.
+        for y in 0..48 {
+            for x in 0..64 {
+                let color = palette.thumbnail[((x * 3 + y * 5) % 16) as usize];
+                image.set_pixel(x, y, &color).unwrap();
+            }
+        }
+
+        let mut thumbnail = PPMThumbnail::default();
+        thumbnail.set_image(&image).unwrap();
+
+        assert!(thumbnail.is_complete());
+        assert_eq!(
+            thumbnail.get_image().unwrap().get_pixels().unwrap(),
+            image.get_pixels().unwrap()
+        );
+    }
+}
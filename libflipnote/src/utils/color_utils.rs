@@ -18,6 +18,10 @@ pub fn hex_to_rgb(hex: &str) -> Result<RgbWrapper> {
     Ok(RgbWrapper::new(r, g, b))
 }
 
+pub fn rgb_to_hex(color: &RgbWrapper) -> String {
+    format!("#{:02X}{:02X}{:02X}", color.r, color.g, color.b)
+}
+
 pub fn thumbnail_pixel_to_rgb(pixel: u8) -> Result<(RgbWrapper, RgbWrapper)> {
     let color1 = hex_to_rgb(PPM_THUMBNAIL_COLORS[(pixel & 0x0F) as usize])?;
     let color2 = hex_to_rgb(PPM_THUMBNAIL_COLORS[((pixel >> 4) & 0x0F) as usize])?;
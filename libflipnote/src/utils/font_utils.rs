@@ -0,0 +1,216 @@
+//! A tiny built-in bitmap font for labelling rendered images, so captions don't need a font file.
+
+use super::image_utils::{ImageWrapper, RgbWrapper};
+
+/// Width of a glyph in pixels, at a scale of 1.
+pub const GLYPH_WIDTH: u32 = 5;
+/// Height of a glyph in pixels, at a scale of 1.
+pub const GLYPH_HEIGHT: u32 = 7;
+/// Horizontal distance from one glyph to the next, leaving a column of space between them.
+pub const GLYPH_ADVANCE: u32 = GLYPH_WIDTH + 1;
+
+/// Printable ASCII from space to `~`, 5 columns per glyph with the top row in the lowest bit.
+const GLYPHS: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // )
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], // *
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // 0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // @
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], // G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], // M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // V
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7F, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7F, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7F, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7F], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7E, 0x09, 0x01, 0x02], // f
+    [0x0C, 0x52, 0x52, 0x52, 0x3E], // g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3D, 0x00], // j
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // l
+    [0x7C, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7C, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7C], // q
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3F, 0x44, 0x40, 0x20], // t
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // u
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // v
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0C, 0x50, 0x50, 0x50, 0x3C], // y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7F, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+/// The width of `text` in pixels when drawn by [`draw_text`], without the space after the last glyph.
+pub fn get_text_width(text: &str, scale: u32) -> u32 {
+    (text.chars().count() as u32 * GLYPH_ADVANCE).saturating_sub(1) * scale
+}
+
+/// Shortens `text` to fit in `width` pixels, ending it with `..` if anything was cut off.
+pub fn fit_text(text: &str, width: u32, scale: u32) -> String {
+    let max_chars = ((width / scale + 1) / GLYPH_ADVANCE) as usize;
+
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+
+    let kept = max_chars.saturating_sub(2);
+
+    text.chars()
+        .take(kept)
+        .chain("..".chars())
+        .take(max_chars)
+        .collect()
+}
+
+/// Draws `text` onto `image` with its top left corner at `x`, `y`, every font pixel becoming a `scale` by `scale` block.
+/// Only printable ASCII is included, anything else is drawn as `?`. Pixels outside the image are left out.
+pub fn draw_text(
+    image: &mut ImageWrapper,
+    text: &str,
+    x: u32,
+    y: u32,
+    scale: u32,
+    color: &RgbWrapper,
+) {
+    let (width, height) = (image.get_width(), image.get_height());
+
+    for (i, c) in text.chars().enumerate() {
+        let glyph = match c {
+            ' '..='~' => &GLYPHS[c as usize - ' ' as usize],
+            _ => &GLYPHS['?' as usize - ' ' as usize],
+        };
+
+        let glyph_x = x + i as u32 * GLYPH_ADVANCE * scale;
+
+        for (column, bits) in glyph.iter().enumerate() {
+            for row in 0..GLYPH_HEIGHT {
+                if bits >> row & 0x1 == 0 {
+                    continue;
+                }
+
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let pixel_x = glyph_x + column as u32 * scale + dx;
+                        let pixel_y = y + row * scale + dy;
+
+                        if pixel_x < width && pixel_y < height {
+                            //set_pixel can't fail for pixels inside the image.
+                            let _ = image.set_pixel(pixel_x, pixel_y, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_width_leaves_out_the_last_space() {
+        assert_eq!(get_text_width("", 1), 0);
+        assert_eq!(get_text_width("a", 1), 5);
+        assert_eq!(get_text_width("ab", 1), 11);
+        assert_eq!(get_text_width("ab", 3), 33);
+    }
+
+    #[test]
+    fn fit_text_cuts_off_with_dots() {
+        assert_eq!(fit_text("Hello", 29, 1), "Hello");
+        assert_eq!(fit_text("Hello world", 35, 1), "Hell..");
+        assert_eq!(fit_text("Hello world", 70, 2), "Hell..");
+        assert_eq!(fit_text("Hello", 11, 1), "..");
+        assert_eq!(fit_text("Hello", 4, 1), "");
+
+        for width in 0..80 {
+            assert!(get_text_width(&fit_text("Hello world", width, 1), 1) <= width);
+        }
+    }
+
+    #[test]
+    fn draw_text_sets_the_glyph_pixels() {
+        let color = RgbWrapper::new(255, 0, 0);
+        let mut image = ImageWrapper::new(8, 8);
+
+        //the exclamation mark is a single column, the third, with a gap above the dot.
+        draw_text(&mut image, "!", 0, 0, 1, &color);
+
+        for y in 0..8 {
+            for x in 0..8 {
+                let expected = x == 2 && y < 7 && y != 5;
+
+                assert_eq!(image.get_pixel(x, y).unwrap() == color, expected);
+            }
+        }
+    }
+}
@@ -19,7 +19,19 @@ pub enum DitherType {
     Bayer8x8,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+/// How an image is fitted into a different aspect ratio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizeMode {
+    /// Scales the image to fit inside the target, filling the borders with a background color.
+    #[default]
+    Fit,
+    /// Scales the image to cover the target, cropping whatever sticks out.
+    Fill,
+    /// Scales both axes independently, ignoring the aspect ratio.
+    Stretch,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct RgbWrapper {
     pub r: u8,
     pub g: u8,
@@ -139,6 +151,23 @@ impl ImageWrapper {
         })
     }
 
+    /// Creates an image from tightly packed RGBA pixels, the inverse of [`ImageWrapper::get_raw_pixels`].
+    pub fn from_raw_pixels(width: u32, height: u32, pixels: Vec<u8>) -> Result<ImageWrapper> {
+        let image = RgbaImage::from_raw(width, height, pixels).ok_or_else(|| {
+            anyhow::anyhow!("Pixel buffer does not match a {width}x{height} image")
+        })?;
+
+        Ok(ImageWrapper { image })
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.image.width()
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.image.height()
+    }
+
     pub fn save_as(&self, path: impl Into<PathBuf>) -> Result<()> {
         let path: PathBuf = path.into();
 
@@ -175,12 +204,82 @@ impl ImageWrapper {
         self.image.as_raw().to_vec()
     }
 
+    /// Copies `image` onto this image with its top left corner at `x`, `y`, replacing the pixels underneath.
+    pub fn paste(&mut self, image: &ImageWrapper, x: u32, y: u32) {
+        imageops::replace(&mut self.image, &image.image, x as i64, y as i64);
+    }
+
     pub fn resize(&self, width: u32, height: u32) -> Result<ImageWrapper> {
         Ok(ImageWrapper {
-            image: resize(&self.image, width + 1, height + 1, FilterType::Nearest),
+            image: resize(&self.image, width, height, FilterType::Nearest),
         })
     }
 
+    /// Enlarges the image by a whole factor, every pixel becoming a `scale` by `scale` block.
+    pub fn scale_nearest(&self, scale: u32) -> ImageWrapper {
+        ImageWrapper {
+            image: resize(
+                &self.image,
+                self.image.width() * scale,
+                self.image.height() * scale,
+                FilterType::Nearest,
+            ),
+        }
+    }
+
+    /// Resizes the image to exactly `width` x `height`, see [`ResizeMode`] for how the aspect ratio is handled.
+    /// Transparent pixels and the borders left by [`ResizeMode::Fit`] are filled with `background`.
+    pub fn resize_with_mode(
+        &self,
+        width: u32,
+        height: u32,
+        mode: ResizeMode,
+        background: &RgbWrapper,
+    ) -> Result<ImageWrapper> {
+        let source_width = self.image.width().max(1) as f32;
+        let source_height = self.image.height().max(1) as f32;
+
+        let scale_x = width as f32 / source_width;
+        let scale_y = height as f32 / source_height;
+
+        let (scaled_width, scaled_height) = match mode {
+            ResizeMode::Stretch => (width, height),
+            ResizeMode::Fit => {
+                let scale = scale_x.min(scale_y);
+                (
+                    ((source_width * scale).round() as u32).clamp(1, width),
+                    ((source_height * scale).round() as u32).clamp(1, height),
+                )
+            }
+            ResizeMode::Fill => {
+                let scale = scale_x.max(scale_y);
+                (
+                    ((source_width * scale).round() as u32).max(width),
+                    ((source_height * scale).round() as u32).max(height),
+                )
+            }
+        };
+
+        let scaled = resize(
+            &self.image,
+            scaled_width,
+            scaled_height,
+            FilterType::Triangle,
+        );
+
+        let background = Rgba([background.r, background.g, background.b, 255]);
+        let mut image = RgbaImage::from_pixel(width, height, background);
+
+        //centers the scaled image, which either leaves borders (fit) or crops the overflow (fill).
+        let offset_x = (width as i64 - scaled_width as i64) / 2;
+        let offset_y = (height as i64 - scaled_height as i64) / 2;
+
+        //blending onto the opaque background also flattens any transparency.
+        imageops::overlay(&mut image, &scaled, offset_x, offset_y);
+
+        Ok(ImageWrapper { image })
+    }
+
     pub fn dither(
         &self,
         dither_type: DitherType,
@@ -1,3 +1,8 @@
 pub mod color_utils;
 pub mod crypto;
+pub mod font_utils;
 pub mod image_utils;
+pub mod upscale_utils;
+
+#[cfg(test)]
+pub(crate) mod test_utils;
@@ -0,0 +1,67 @@
+//! Helpers shared by the tests.
+
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use crate::ppm::{
+    file::PPMFile,
+    file_builder::PPMFileBuilder,
+    frames::{
+        frame::PPMFrame,
+        frame_header::{PPMLayerColor, PPMPaperColor},
+    },
+};
+
+/// An empty directory for one test, under the system's temp directory.
+pub fn get_temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("libflipnote-{}-{}", std::process::id(), name));
+
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
+
+/// Writes an executable shell script, used to stand in for ffmpeg.
+pub fn write_script(dir: &Path, name: &str, body: &str) -> PathBuf {
+    let path = dir.join(name);
+
+    std::fs::write(&path, format!("#!/bin/sh\n{body}")).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    path
+}
+
+/// A red and blue frame with a different pattern for every `seed`, on white paper for even seeds and black for odd ones.
+pub fn get_test_frame(seed: usize) -> PPMFrame {
+    let mut frame = PPMFrame::default();
+
+    let header = frame.get_header_mut();
+    header.set_paper_color(PPMPaperColor::from(seed % 2));
+    header.set_layer_color(1, PPMLayerColor::Red).unwrap();
+    header.set_layer_color(2, PPMLayerColor::Blue).unwrap();
+
+    for y in 0..192 {
+        for x in 0..256 {
+            let layer_1 = (x + seed * 7).is_multiple_of(13) || y == seed;
+            let layer_2 = (x / 16 + y / 16 + seed).is_multiple_of(3);
+
+            frame.get_layer_mut(1).unwrap().set(x, y, layer_1).unwrap();
+            frame.get_layer_mut(2).unwrap().set(x, y, layer_2).unwrap();
+        }
+    }
+
+    frame
+}
+
+/// An unsigned flipnote of the test frames of `seeds`, played at `speed`.
+pub fn get_test_file(seeds: &[usize], speed: u8) -> PPMFile {
+    PPMFileBuilder::new()
+        .frames(seeds.iter().copied().map(get_test_frame).collect())
+        .speed(speed)
+        .seed(1)
+        .build()
+        .unwrap()
+}
@@ -0,0 +1,263 @@
+/// Pixel art upscalers for rendering frames above 256x192.
+/// They only ever copy existing pixels, so a frame keeps its 3 colors and can still be written as indices.
+/// That rules out hqx and xBR, which blend neighboring colors into new ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Upscaler {
+    /// Every pixel becomes a square block.
+    #[default]
+    Nearest,
+    /// Scale2x, also known as EPX or AdvMAME2x. Rounds off diagonal steps, applied repeatedly for 4x, 8x and so on.
+    Scale2x,
+    /// Scale3x, also known as AdvMAME3x. Like Scale2x but for factors of 3.
+    Scale3x,
+}
+
+impl Upscaler {
+    /// The factor of a single pass.
+    pub fn get_factor(&self) -> u32 {
+        match self {
+            Upscaler::Nearest => 1,
+            Upscaler::Scale2x => 2,
+            Upscaler::Scale3x => 3,
+        }
+    }
+}
+
+/// Scales indexed pixels by `scale`, returning the new pixels row by row.
+/// `classes` maps every index to the indices it should count as equal to, so colors that render the same are treated as one.
+/// The upscaler runs as long as it divides the remaining scale, whatever is left over is done with nearest neighbor.
+pub fn upscale_indexed(
+    pixels: &[u8],
+    width: usize,
+    height: usize,
+    scale: u32,
+    upscaler: Upscaler,
+    classes: &[u8],
+) -> Vec<u8> {
+    let mut pixels = pixels.to_vec();
+    let (mut width, mut height) = (width, height);
+    let mut remaining = scale;
+
+    let factor = upscaler.get_factor();
+
+    while factor > 1 && remaining.is_multiple_of(factor) {
+        pixels = match upscaler {
+            Upscaler::Scale2x => scale2x(&pixels, width, height, classes),
+            _ => scale3x(&pixels, width, height, classes),
+        };
+
+        width *= factor as usize;
+        height *= factor as usize;
+        remaining /= factor;
+    }
+
+    if remaining > 1 {
+        pixels = nearest(&pixels, width, height, remaining as usize);
+    }
+
+    pixels
+}
+
+fn nearest(pixels: &[u8], width: usize, height: usize, scale: usize) -> Vec<u8> {
+    let mut scaled = Vec::with_capacity(pixels.len() * scale * scale);
+
+    for y in 0..height {
+        let row = pixels[y * width..(y + 1) * width]
+            .iter()
+            .flat_map(|pixel| std::iter::repeat_n(*pixel, scale))
+            .collect::<Vec<u8>>();
+
+        for _ in 0..scale {
+            scaled.extend_from_slice(&row);
+        }
+    }
+
+    scaled
+}
+
+/// Returns the 3x3 neighborhood of a pixel, A to I row by row with E in the middle. Pixels past the edge repeat the border.
+fn get_neighborhood(pixels: &[u8], width: usize, height: usize, x: usize, y: usize) -> [u8; 9] {
+    let mut neighborhood = [0u8; 9];
+
+    for (i, (dx, dy)) in (-1isize..=1)
+        .flat_map(|dy| (-1isize..=1).map(move |dx| (dx, dy)))
+        .enumerate()
+    {
+        let nx = (x as isize + dx).clamp(0, width as isize - 1) as usize;
+        let ny = (y as isize + dy).clamp(0, height as isize - 1) as usize;
+
+        neighborhood[i] = pixels[ny * width + nx];
+    }
+
+    neighborhood
+}
+
+fn scale2x(pixels: &[u8], width: usize, height: usize, classes: &[u8]) -> Vec<u8> {
+    let mut scaled = vec![0u8; pixels.len() * 4];
+    let eq = |a: u8, b: u8| classes[a as usize] == classes[b as usize];
+
+    for y in 0..height {
+        for x in 0..width {
+            let [_, b, _, d, e, f, _, h, _] = get_neighborhood(pixels, width, height, x, y);
+
+            let mut block = [e; 4];
+
+            if !eq(b, h) && !eq(d, f) {
+                if eq(d, b) {
+                    block[0] = d;
+                }
+                if eq(b, f) {
+                    block[1] = f;
+                }
+                if eq(d, h) {
+                    block[2] = d;
+                }
+                if eq(h, f) {
+                    block[3] = f;
+                }
+            }
+
+            for (i, pixel) in block.iter().enumerate() {
+                scaled[(y * 2 + i / 2) * width * 2 + x * 2 + i % 2] = *pixel;
+            }
+        }
+    }
+
+    scaled
+}
+
+fn scale3x(pixels: &[u8], width: usize, height: usize, classes: &[u8]) -> Vec<u8> {
+    let mut scaled = vec![0u8; pixels.len() * 9];
+    let eq = |a: u8, b: u8| classes[a as usize] == classes[b as usize];
+
+    for y in 0..height {
+        for x in 0..width {
+            let [a, b, c, d, e, f, g, h, i] = get_neighborhood(pixels, width, height, x, y);
+
+            let mut block = [e; 9];
+
+            if !eq(b, h) && !eq(d, f) {
+                if eq(d, b) {
+                    block[0] = d;
+                }
+                if (eq(d, b) && !eq(e, c)) || (eq(b, f) && !eq(e, a)) {
+                    block[1] = b;
+                }
+                if eq(b, f) {
+                    block[2] = f;
+                }
+                if (eq(d, b) && !eq(e, g)) || (eq(d, h) && !eq(e, a)) {
+                    block[3] = d;
+                }
+                if (eq(b, f) && !eq(e, i)) || (eq(h, f) && !eq(e, c)) {
+                    block[5] = f;
+                }
+                if eq(d, h) {
+                    block[6] = d;
+                }
+                if (eq(d, h) && !eq(e, i)) || (eq(h, f) && !eq(e, g)) {
+                    block[7] = h;
+                }
+                if eq(h, f) {
+                    block[8] = f;
+                }
+            }
+
+            for (j, pixel) in block.iter().enumerate() {
+                scaled[(y * 3 + j / 3) * width * 3 + x * 3 + j % 3] = *pixel;
+            }
+        }
+    }
+
+    scaled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //a diagonal line from the top left to the bottom right.
+    const DIAGONAL: [u8; 9] = [1, 0, 0, 0, 1, 0, 0, 0, 1];
+    const CLASSES: [u8; 2] = [0, 1];
+
+    fn upscale(pixels: &[u8], width: usize, scale: u32, upscaler: Upscaler) -> Vec<u8> {
+        upscale_indexed(
+            pixels,
+            width,
+            pixels.len() / width,
+            scale,
+            upscaler,
+            &CLASSES,
+        )
+    }
+
+    #[test]
+    fn scale2x_rounds_off_diagonals() {
+        #[rustfmt::skip]
+        let expected = [
+            1, 1, 0, 0, 0, 0,
+            1, 0, 1, 0, 0, 0,
+            0, 1, 1, 1, 0, 0,
+            0, 0, 1, 1, 1, 0,
+            0, 0, 0, 1, 0, 1,
+            0, 0, 0, 0, 1, 1,
+        ];
+
+        assert_eq!(upscale(&DIAGONAL, 3, 2, Upscaler::Scale2x), expected);
+    }
+
+    #[test]
+    fn scale3x_rounds_off_diagonals() {
+        #[rustfmt::skip]
+        let expected = [
+            1, 1, 1, 0, 0, 0, 0, 0, 0,
+            1, 1, 0, 1, 0, 0, 0, 0, 0,
+            1, 0, 0, 1, 0, 0, 0, 0, 0,
+            0, 1, 1, 1, 1, 1, 0, 0, 0,
+            0, 0, 0, 1, 1, 1, 0, 0, 0,
+            0, 0, 0, 1, 1, 1, 1, 1, 0,
+            0, 0, 0, 0, 0, 1, 0, 0, 1,
+            0, 0, 0, 0, 0, 1, 0, 1, 1,
+            0, 0, 0, 0, 0, 0, 1, 1, 1,
+        ];
+
+        assert_eq!(upscale(&DIAGONAL, 3, 3, Upscaler::Scale3x), expected);
+    }
+
+    #[test]
+    fn leftover_factors_use_nearest_neighbor() {
+        let scale2x = upscale(&DIAGONAL, 3, 2, Upscaler::Scale2x);
+        let scale3x = upscale(&DIAGONAL, 3, 3, Upscaler::Scale3x);
+
+        //6 is one pass and a nearest neighbor 3x or 2x after it.
+        assert_eq!(
+            upscale(&DIAGONAL, 3, 6, Upscaler::Scale2x),
+            upscale(&scale2x, 6, 3, Upscaler::Nearest)
+        );
+        assert_eq!(
+            upscale(&DIAGONAL, 3, 6, Upscaler::Scale3x),
+            upscale(&scale3x, 9, 2, Upscaler::Nearest)
+        );
+
+        //4 is two passes.
+        assert_eq!(
+            upscale(&DIAGONAL, 3, 4, Upscaler::Scale2x),
+            upscale(&scale2x, 6, 2, Upscaler::Scale2x)
+        );
+
+        //factors the upscaler doesn't divide are nearest neighbor only.
+        assert_eq!(
+            upscale(&DIAGONAL, 3, 3, Upscaler::Scale2x),
+            upscale(&DIAGONAL, 3, 3, Upscaler::Nearest)
+        );
+        assert_eq!(upscale(&DIAGONAL, 3, 1, Upscaler::Scale3x), DIAGONAL);
+    }
+
+    #[test]
+    fn equal_classes_are_not_smoothed() {
+        //with both indices in the same class there are no edges to round off.
+        let scaled = upscale_indexed(&DIAGONAL, 3, 3, 2, Upscaler::Scale2x, &[0, 0]);
+
+        assert_eq!(scaled, upscale(&DIAGONAL, 3, 2, Upscaler::Nearest));
+    }
+}